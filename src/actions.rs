@@ -0,0 +1,257 @@
+use fuser::FileType;
+use libc::{c_int, ENOENT};
+use std::fs;
+
+use github;
+use passthrough;
+use views::{self, encodeName, ViewNode, ViewPath, Views};
+
+// `.actions/workflows/<workflow>/runs/<id>/`: the run as `meta.json`, `jobs/<job>/log`
+// and `artifacts/<name>.zip`. Logs are fetched when they are opened and kept in the
+// downloads cache once the run is complete, artifacts are downloaded when read.
+//
+// Only the latest RUNS_LISTED runs of a workflow are listed, older ones can still be
+// entered by id.
+
+const RUNS_LISTED: u32 = 100;
+const RUN_FILES: &[&str] = &["artifacts", "jobs", "meta.json"];
+
+struct Run {
+    workflowId: String,
+    isComplete: bool,
+}
+
+// Rows of `@tsv` output with `fieldCount` fields.
+fn parseRows(output: &str, fieldCount: usize) -> Vec<Vec<String>> {
+    output
+        .lines()
+        .map(|line| line.split("\t").map(|field| field.to_string()).collect::<Vec<String>>())
+        .filter(|fields| fields.len() == fieldCount)
+        .collect()
+}
+
+// (directory name, id): workflows are named after their file, e.g. `ci.yml`.
+fn listWorkflows(views: &mut Views, fullRepositoryName: &str) -> Result<Vec<(String, String)>, c_int> {
+    let output = views.gh(&[
+        "api", "--paginate", &format!("repos/{}/actions/workflows?per_page=100", fullRepositoryName),
+        "--jq", ".workflows[] | [.path, .id] | @tsv",
+    ])?;
+    Ok(parseRows(&output, 2)
+        .into_iter()
+        .map(|fields| (fields[0].rsplit("/").next().unwrap_or("").to_string(), fields[1].clone()))
+        .collect())
+}
+
+fn workflowId(views: &mut Views, fullRepositoryName: &str, name: &str) -> Result<Option<String>, c_int> {
+    Ok(listWorkflows(views, fullRepositoryName)?
+        .into_iter()
+        .find(|&(ref workflowName, _)| workflowName == name)
+        .map(|(_, id)| id))
+}
+
+fn listRuns(views: &mut Views, fullRepositoryName: &str, workflowId: &str) -> Result<Vec<String>, c_int> {
+    let output = views.gh(&[
+        "api", &format!("repos/{}/actions/workflows/{}/runs?per_page={}", fullRepositoryName, workflowId, RUNS_LISTED),
+        "--jq", ".workflow_runs[].id",
+    ])?;
+    Ok(output.lines().filter(|id| id.len() > 0).map(|id| id.to_string()).collect())
+}
+
+fn getRun(views: &mut Views, fullRepositoryName: &str, id: &str) -> Result<Option<Run>, c_int> {
+    if id.parse::<u64>().is_err() {
+        return Ok(None);
+    }
+    let output = match views.gh(&[
+        "api", &format!("repos/{}/actions/runs/{}", fullRepositoryName, id), "--jq", "[.workflow_id, .status] | @tsv",
+    ]) {
+        Ok(output) => output,
+        Err(ENOENT) => return Ok(None),
+        Err(error) => return Err(error),
+    };
+    Ok(parseRows(&output, 2).into_iter().next().map(|fields| Run {
+        workflowId: fields[0].clone(),
+        isComplete: fields[1] == "completed",
+    }))
+}
+
+// (directory name, id) of the jobs of the latest attempt.
+fn listJobs(views: &mut Views, fullRepositoryName: &str, runId: &str) -> Result<Vec<(String, String)>, c_int> {
+    let output = views.gh(&[
+        "api", "--paginate", &format!("repos/{}/actions/runs/{}/jobs?per_page=100", fullRepositoryName, runId),
+        "--jq", ".jobs[] | [.name, .id] | @tsv",
+    ])?;
+    Ok(parseRows(&output, 2).into_iter().map(|fields| (encodeName(&fields[0]), fields[1].clone())).collect())
+}
+
+// (file name, id, size) of the artifacts that did not expire.
+fn listArtifacts(views: &mut Views, fullRepositoryName: &str, runId: &str) -> Result<Vec<(String, String, u64)>, c_int> {
+    let output = views.gh(&[
+        "api", "--paginate", &format!("repos/{}/actions/runs/{}/artifacts?per_page=100", fullRepositoryName, runId),
+        "--jq", ".artifacts[] | select(.expired | not) | [.name, .id, .size_in_bytes] | @tsv",
+    ])?;
+    Ok(parseRows(&output, 3)
+        .into_iter()
+        .map(|fields| (encodeName(&fields[0]) + ".zip", fields[1].clone(), fields[2].parse::<u64>().unwrap_or(0)))
+        .collect())
+}
+
+fn jobId(views: &mut Views, fullRepositoryName: &str, runId: &str, name: &str) -> Result<Option<String>, c_int> {
+    Ok(listJobs(views, fullRepositoryName, runId)?
+        .into_iter()
+        .find(|&(ref jobName, _)| jobName == name)
+        .map(|(_, id)| id))
+}
+
+fn jobLogEndpoint(fullRepositoryName: &str, jobId: &str) -> String {
+    format!("repos/{}/actions/jobs/{}/logs", fullRepositoryName, jobId)
+}
+
+fn jobLogFileName(jobId: &str) -> String {
+    format!("job-{}.log", jobId)
+}
+
+// The log of a job still running can change, it is only kept for CACHE_TTL.
+fn jobLog(views: &mut Views, fullRepositoryName: &str, jobId: &str, isComplete: bool) -> Result<Vec<u8>, c_int> {
+    let endpoint = jobLogEndpoint(fullRepositoryName, jobId);
+    if !isComplete {
+        return views.ghFile(&["api", &endpoint]);
+    }
+    let logPath = views.downloadsDirectory.join(jobLogFileName(jobId));
+    if !logPath.exists() {
+        github::download(&endpoint, &logPath).map_err(|error| views::errorCode(&error))?;
+    }
+    fs::read(&logPath).map_err(|error| passthrough::errorCode(&error))
+}
+
+// The run of `workflows/<workflow>/runs/<id>`, when `rest` is below such a directory.
+fn runIn(views: &mut Views, fullRepositoryName: &str, rest: &[String]) -> Result<Option<Run>, c_int> {
+    if rest.len() < 4 || rest[0] != "workflows" || rest[2] != "runs" {
+        return Ok(None);
+    }
+    let workflowId = match workflowId(views, fullRepositoryName, &rest[1])? {
+        Some(workflowId) => workflowId,
+        None => return Ok(None),
+    };
+    Ok(getRun(views, fullRepositoryName, &rest[3])?.filter(|run| run.workflowId == workflowId))
+}
+
+pub fn resolve(views: &mut Views, path: &ViewPath) -> Result<Option<ViewNode>, c_int> {
+    let rest = &path.rest;
+    match rest.len() {
+        0 => return Ok(Some(ViewNode::Directory)),
+        1 => return Ok(if rest[0] == "workflows" { Some(ViewNode::Directory) } else { None }),
+        2 | 3 => {
+            let isWorkflow = rest[0] == "workflows" && workflowId(views, &path.repository, &rest[1])?.is_some();
+            let isDirectory = isWorkflow && (rest.len() == 2 || rest[2] == "runs");
+            return Ok(if isDirectory { Some(ViewNode::Directory) } else { None });
+        },
+        _ => (),
+    }
+    let run = match runIn(views, &path.repository, rest)? {
+        Some(run) => run,
+        None => return Ok(None),
+    };
+    let runId = &rest[3];
+    let node = match (rest.get(4).map(|name| name.as_str()), rest.len()) {
+        (None, _) => ViewNode::Directory,
+        (Some("meta.json"), 5) => {
+            ViewNode::File(views.ghFile(&["api", &format!("repos/{}/actions/runs/{}", path.repository, runId)])?)
+        },
+        (Some("jobs"), 5) | (Some("artifacts"), 5) => ViewNode::Directory,
+        (Some("jobs"), 6) | (Some("jobs"), 7) => {
+            let jobId = match jobId(views, &path.repository, runId, &rest[5])? {
+                Some(jobId) => jobId,
+                None => return Ok(None),
+            };
+            match rest.get(6).map(|name| name.as_str()) {
+                None => ViewNode::Directory,
+                Some("log") => {
+                    // a log already downloaded has its size, others are fetched on open
+                    let fileName = jobLogFileName(&jobId);
+                    match fs::metadata(views.downloadsDirectory.join(&fileName)) {
+                        Ok(ref metadata) if run.isComplete => ViewNode::Download {
+                            endpoint: jobLogEndpoint(&path.repository, &jobId),
+                            fileName: fileName,
+                            size: metadata.len(),
+                        },
+                        _ => views.deferredNode(&path.fullPath),
+                    }
+                },
+                Some(_) => return Ok(None),
+            }
+        },
+        (Some("artifacts"), 6) => {
+            match listArtifacts(views, &path.repository, runId)?.into_iter().find(|&(ref name, _, _)| *name == rest[5]) {
+                Some((_, artifactId, size)) => ViewNode::Download {
+                    endpoint: format!("repos/{}/actions/artifacts/{}/zip", path.repository, artifactId),
+                    fileName: format!("artifact-{}.zip", artifactId),
+                    size: size,
+                },
+                None => return Ok(None),
+            }
+        },
+        _ => return Ok(None),
+    };
+    Ok(Some(node))
+}
+
+// The contents of `workflows/<workflow>/runs/<id>/jobs/<job>/log`.
+pub fn render(views: &mut Views, path: &ViewPath) -> Result<Vec<u8>, c_int> {
+    let rest = &path.rest;
+    if rest.len() != 7 || rest[4] != "jobs" || rest[6] != "log" {
+        return Err(ENOENT);
+    }
+    let run = runIn(views, &path.repository, rest)?.ok_or(ENOENT)?;
+    let jobId = jobId(views, &path.repository, &rest[3], &rest[5])?.ok_or(ENOENT)?;
+    jobLog(views, &path.repository, &jobId, run.isComplete)
+}
+
+pub fn list(views: &mut Views, path: &ViewPath) -> Result<Vec<(String, FileType)>, c_int> {
+    let rest = &path.rest;
+    match rest.len() {
+        0 => return Ok(vec![("workflows".to_string(), FileType::Directory)]),
+        1 if rest[0] == "workflows" => {
+            return Ok(listWorkflows(views, &path.repository)?
+                .into_iter()
+                .map(|(name, _)| (name, FileType::Directory))
+                .collect());
+        },
+        2 if resolve(views, path)?.is_some() => return Ok(vec![("runs".to_string(), FileType::Directory)]),
+        3 => {
+            let workflowId = match rest[0] == "workflows" && rest[2] == "runs" {
+                true => workflowId(views, &path.repository, &rest[1])?.ok_or(ENOENT)?,
+                false => return Err(ENOENT),
+            };
+            return Ok(listRuns(views, &path.repository, &workflowId)?
+                .into_iter()
+                .map(|id| (id, FileType::Directory))
+                .collect());
+        },
+        _ => (),
+    }
+    if runIn(views, &path.repository, rest)?.is_none() {
+        return Err(ENOENT);
+    }
+    let runId = &rest[3];
+    match (rest.get(4).map(|name| name.as_str()), rest.len()) {
+        (None, _) => Ok(RUN_FILES
+            .iter()
+            .map(|name| {
+                let kind = if *name == "meta.json" { FileType::RegularFile } else { FileType::Directory };
+                (name.to_string(), kind)
+            })
+            .collect()),
+        (Some("jobs"), 5) => Ok(listJobs(views, &path.repository, runId)?
+            .into_iter()
+            .map(|(name, _)| (name, FileType::Directory))
+            .collect()),
+        (Some("jobs"), 6) if listJobs(views, &path.repository, runId)?.iter().any(|&(ref name, _)| *name == rest[5]) => {
+            Ok(vec![("log".to_string(), FileType::RegularFile)])
+        },
+        (Some("artifacts"), 5) => Ok(listArtifacts(views, &path.repository, runId)?
+            .into_iter()
+            .map(|(name, _, _)| (name, FileType::RegularFile))
+            .collect()),
+        _ => Err(ENOENT),
+    }
+}
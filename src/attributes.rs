@@ -0,0 +1,142 @@
+use fuser::{FileAttr, FileType};
+use std::path::Path;
+use std::time::SystemTime;
+
+use fetcher;
+use tree::{TreeLocation, TreeNode};
+
+// Attributes of the nodes the mount makes up (accounts, repositories, remote trees,
+// special directories), and extended attributes with what GitHub knows about a node,
+// read with `getfattr -d`. Extended attributes come from the same data as the node
+// itself: `gh repo list`, the tree listings (fetched like for a lookup when they are
+// not cached yet, with the default branch) and, for clones, local `git` commands.
+
+const BLOCK_SIZE: u32 = 4096;
+
+// Owner of every node: the mounting user unless `uid` / `gid` are configured.
+#[derive(Clone, Copy)]
+pub struct Owner {
+    pub uid: u32,
+    pub gid: u32,
+}
+
+// Whether the `access` mask (`R_OK`, `W_OK`, `X_OK`) is granted by the node's
+// permission bits: the owner's for its owner, the group's for its group, the others'
+// for everyone else.
+pub fn isAccessAllowed(attr: &FileAttr, uid: u32, gid: u32, mask: i32) -> bool {
+    let bits = match () {
+        _ if uid == attr.uid => attr.perm >> 6,
+        _ if gid == attr.gid => attr.perm >> 3,
+        _ => attr.perm,
+    } & 0o7;
+    mask as u16 & 0o7 & !bits == 0
+}
+
+// Directories get a link count of 1 here, which tells `find` their number of
+// subdirectories is unknown. Callers that know it set `2 + subdirectories`.
+pub fn nodeAttr(ino: u64, kind: FileType, perm: u16, size: u64, ts: SystemTime, owner: Owner) -> FileAttr {
+    FileAttr {
+        ino: ino,
+        size: size,
+        blocks: (size + 511) / 512,
+        atime: ts,
+        mtime: ts,
+        ctime: ts,
+        crtime: ts,
+        kind: kind,
+        perm: perm,
+        nlink: 1,
+        uid: owner.uid,
+        gid: owner.gid,
+        rdev: 0,
+        flags: 0,
+        blksize: BLOCK_SIZE,
+    }
+}
+
+// Every attribute of the mount is in this namespace, others are never looked up.
+pub const ATTRIBUTE_PREFIX: &str = "user.github.";
+pub const SHA_ATTRIBUTE: &str = "user.github.sha";
+pub const URL_ATTRIBUTE: &str = "user.github.url";
+pub const REF_ATTRIBUTE: &str = "user.github.ref";
+pub const MODE_ATTRIBUTE: &str = "user.github.mode";
+pub const STARS_ATTRIBUTE: &str = "user.github.stars";
+pub const VISIBILITY_ATTRIBUTE: &str = "user.github.visibility";
+
+// What `gh repo list` says about a repository, also used for the times of its
+// directory.
+pub struct RepositoryDetails {
+    pub stars: u64,
+    pub visibility: String,
+    pub pushedAt: Option<SystemTime>,
+    pub createdAt: Option<SystemTime>,
+}
+
+pub fn webUrl(fullRepositoryName: &str, reference: &str, relativePath: &str, isDirectory: bool) -> String {
+    match (relativePath.len(), isDirectory) {
+        (0, _) => format!("https://github.com/{}/tree/{}", fullRepositoryName, reference),
+        (_, true) => format!("https://github.com/{}/tree/{}/{}", fullRepositoryName, reference, relativePath),
+        (_, false) => format!("https://github.com/{}/blob/{}/{}", fullRepositoryName, reference, relativePath),
+    }
+}
+
+pub fn repositoryAttributes(fullRepositoryName: &str, details: Option<&RepositoryDetails>) -> Vec<(&'static str, String)> {
+    let mut attributes = vec![(URL_ATTRIBUTE, format!("https://github.com/{}", fullRepositoryName))];
+    if let Some(details) = details {
+        attributes.push((STARS_ATTRIBUTE, details.stars.to_string()));
+        attributes.push((VISIBILITY_ATTRIBUTE, details.visibility.clone()));
+    }
+    attributes
+}
+
+// Files edited in the overlay have no sha or mode yet, only where they will go.
+pub fn treeAttributes(location: &TreeLocation, node: &TreeNode) -> Vec<(&'static str, String)> {
+    let isDirectory = match *node {
+        TreeNode::Upper(ref upperPath) => upperPath.is_dir(),
+        TreeNode::Remote(ref entry) => entry.kind == "tree",
+        TreeNode::Root => true,
+    };
+    let mut attributes = vec![
+        (REF_ATTRIBUTE, location.root.reference.clone()),
+        (URL_ATTRIBUTE, webUrl(&location.root.repository, &location.root.reference, &location.relativePath, isDirectory)),
+    ];
+    if let TreeNode::Remote(ref entry) = *node {
+        attributes.push((SHA_ATTRIBUTE, entry.sha.clone()));
+        attributes.push((MODE_ATTRIBUTE, entry.mode.clone()));
+    }
+    attributes
+}
+
+// The sha and mode of a cloned file are the ones of its last commit, like in the tree
+// on GitHub. Only the `git` commands `wanted` needs are run (all of them for None).
+pub fn cloneAttributes(
+    fullRepositoryName: &str,
+    clonePath: &Path,
+    relativePath: &str,
+    wanted: Option<&str>,
+) -> Vec<(&'static str, String)> {
+    let isWanted = |name: &str| wanted.map(|wanted| wanted == name).unwrap_or(true);
+    let mut attributes = Vec::new();
+    if isWanted(REF_ATTRIBUTE) || isWanted(URL_ATTRIBUTE) {
+        match fetcher::git(clonePath, &["rev-parse", "--abbrev-ref", "HEAD"]) {
+            Ok(reference) => attributes.push((REF_ATTRIBUTE, reference)),
+            Err(_) => return Vec::new(),
+        }
+    }
+    let mut isDirectory = clonePath.join(relativePath).is_dir();
+    if relativePath.len() > 0 && (isWanted(SHA_ATTRIBUTE) || isWanted(MODE_ATTRIBUTE)) {
+        // `<mode> <type> <sha>\t<path>`
+        let entry = fetcher::git(clonePath, &["ls-tree", "HEAD", "--", relativePath]).unwrap_or(String::new());
+        let fields: Vec<&str> = entry.split(|character| character == ' ' || character == '\t').collect();
+        if let [mode, kind, sha, ..] = fields.as_slice() {
+            attributes.push((SHA_ATTRIBUTE, sha.to_string()));
+            attributes.push((MODE_ATTRIBUTE, mode.to_string()));
+            isDirectory = *kind == "tree";
+        }
+    }
+    let url = attributes.first().map(|&(_, ref reference)| webUrl(fullRepositoryName, reference, relativePath, isDirectory));
+    if let Some(url) = url {
+        attributes.push((URL_ATTRIBUTE, url));
+    }
+    attributes
+}
@@ -0,0 +1,100 @@
+use std::fs;
+use std::process::Command;
+
+use config::MountConfig;
+use storage::Storage;
+
+// `owner/repo` with the characters GitHub allows in names, so joining it to the clones
+// directory can never point outside of it.
+pub fn isValidRepositoryName(fullRepositoryName: &str) -> bool {
+    let pathParts: Vec<&str> = fullRepositoryName.split("/").collect();
+    pathParts.len() == 2 && pathParts.iter().all(|pathPart| {
+        pathPart.len() > 0
+            && *pathPart != "."
+            && *pathPart != ".."
+            && pathPart.chars().all(|character| character.is_ascii_alphanumeric() || "-_.".contains(character))
+    })
+}
+
+// Runs on the thread serving the FUSE request that asked for the clone (a `.control`
+// write or the lookup of an `auto_clone` repository), so the whole mount waits for it.
+pub fn cloneRepository(config: &MountConfig, storage: &Storage, fullRepositoryName: &str) -> Result<(), String> {
+    if !isValidRepositoryName(fullRepositoryName) {
+        return Err(format!("{:?} is not a repository name", fullRepositoryName));
+    }
+    let clonePath = storage.clonePath(fullRepositoryName);
+    if !clonePath.starts_with(&storage.clonesDirectory) {
+        return Err(format!("{} is outside of the clones directory", clonePath.display()));
+    }
+    let pathToPersist = clonePath.display().to_string();
+    if clonePath.join(".git").exists() {
+        return Ok(());
+    }
+    // whatever is already there is not ours to remove if the clone fails
+    let isEmpty = fs::read_dir(&clonePath).map(|mut children| children.next().is_none()).unwrap_or(true);
+    if !isEmpty {
+        return Err(format!("{} already exists and is not a clone", pathToPersist));
+    }
+    let ownerPath = clonePath.parent().unwrap().to_path_buf();
+    let hasCreatedOwner = !ownerPath.exists();
+    let hasCreatedClone = !clonePath.exists();
+    fs::create_dir_all(&pathToPersist)
+        .map_err(|error| format!("could not create {}: {}", pathToPersist, error))?;
+
+    let strategy = config.cloneStrategyFor(fullRepositoryName);
+    let mut args = vec![
+        "repo".to_string(), "clone".to_string(), fullRepositoryName.to_string(), pathToPersist.clone()
+    ];
+    let gitFlags = strategy.gitFlags();
+    if gitFlags.len() > 0 {
+        args.push("--".to_string());
+        args.extend(gitFlags);
+    }
+    let result = match Command::new("gh").args(&args).output() {
+        Ok(ref cloneOutput) if cloneOutput.status.success() => match strategy.sparse {
+            Some(ref sparsePaths) => setSparseCheckout(&pathToPersist, sparsePaths),
+            None => Ok(()),
+        },
+        Ok(cloneOutput) => Err(String::from_utf8_lossy(&cloneOutput.stderr).trim().to_string()),
+        Err(error) => Err(format!("could not run gh {}: {}", args.join(" "), error)),
+    };
+    if result.is_err() && hasCreatedClone {
+        // leaving the directory behind would make lookup() treat the repository as cloned
+        let _ = fs::remove_dir_all(&pathToPersist);
+        if hasCreatedOwner {
+            let _ = fs::remove_dir(&ownerPath);
+        }
+    }
+    result
+}
+
+fn setSparseCheckout(pathToPersist: &str, sparsePaths: &Vec<String>) -> Result<(), String> {
+    let mut args = vec!["-C", pathToPersist, "sparse-checkout", "set"];
+    args.extend(sparsePaths.iter().map(|path| path.as_str()));
+    let sparseOutput = Command::new("git")
+        .args(&args)
+        .output()
+        .map_err(|error| format!("could not run git {}: {}", args.join(" "), error))?;
+    match sparseOutput.status.success() {
+        true => Ok(()),
+        false => Err(String::from_utf8_lossy(&sparseOutput.stderr).trim().to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rejectsNamesOutsideTheClonesDirectory() {
+        assert!(isValidRepositoryName("cli/cli"));
+        assert!(isValidRepositoryName("some-org/repo_name.js"));
+        assert!(!isValidRepositoryName("../overlays"));
+        assert!(!isValidRepositoryName("cli/.."));
+        assert!(!isValidRepositoryName("./cli"));
+        assert!(!isValidRepositoryName("cli/"));
+        assert!(!isValidRepositoryName("cli"));
+        assert!(!isValidRepositoryName("cli/cli/extra"));
+        assert!(!isValidRepositoryName("cli/my repo"));
+    }
+}
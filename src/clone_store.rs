@@ -0,0 +1,273 @@
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::mem;
+use std::os::unix::fs::MetadataExt;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::sync::{Arc, Mutex};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use clone;
+use config::MountConfig;
+use storage::Storage;
+
+// Access times are only written back to the index when they moved by more than this,
+// so browsing a clone does not rewrite the index on every lookup.
+const ACCESS_PERSIST_INTERVAL: u64 = 60;
+
+struct CloneEntry {
+    lastAccess: u64,
+    diskUsage: u64,
+}
+
+// Keeps track of the clones below the clones directory (last access and disk usage,
+// persisted in `<metadata>/clones`) and evicts the least recently used clean ones
+// whenever the total goes over `storage_quota`.
+pub struct CloneStore {
+    clonesDirectory: PathBuf,
+    indexPath: PathBuf,
+    quota: Option<u64>,
+    entries: HashMap<String, CloneEntry>,
+    // number of files open in each clone through the mount
+    openFiles: HashMap<String, u32>,
+    // clones a fetch is running in, shared with the Fetcher; evicting a clone holds it
+    // here too, so no fetch starts in a clone being removed
+    fetching: Arc<Mutex<HashSet<String>>>,
+}
+
+impl CloneStore {
+    pub fn load(config: &MountConfig, storage: &Storage) -> CloneStore {
+        let mut cloneStore = CloneStore {
+            clonesDirectory: storage.clonesDirectory.clone(),
+            indexPath: storage.metadataDirectory.join("clones"),
+            quota: config.storageQuota,
+            entries: HashMap::new(),
+            openFiles: HashMap::new(),
+            fetching: Arc::new(Mutex::new(HashSet::new())),
+        };
+        cloneStore.refresh(false);
+        cloneStore
+    }
+    // Re-reads the index and the clones directory, clones can also be made or removed
+    // by `gh mount clone` running in another process.
+    fn refresh(&mut self, recomputeUsage: bool) {
+        let mut entries = HashMap::new();
+        if let Ok(contents) = fs::read_to_string(&self.indexPath) {
+            for line in contents.lines() {
+                let fields: Vec<&str> = line.split_whitespace().collect();
+                if fields.len() != 3 {
+                    continue;
+                }
+                let lastAccess = fields[1].parse::<u64>().unwrap_or(0);
+                let diskUsage = fields[2].parse::<u64>().unwrap_or(0);
+                entries.insert(fields[0].to_string(), CloneEntry { lastAccess, diskUsage });
+            }
+        }
+        let mut previousEntries = mem::replace(&mut self.entries, HashMap::new());
+        for fullRepositoryName in listClones(&self.clonesDirectory) {
+            let clonePath = self.clonesDirectory.join(&fullRepositoryName);
+            let mut entry = match entries.remove(&fullRepositoryName) {
+                Some(entry) => entry,
+                None => CloneEntry {
+                    lastAccess: modifiedAt(&clonePath),
+                    diskUsage: 0,
+                },
+            };
+            // touches of this process that were not persisted yet
+            if let Some(previousEntry) = previousEntries.remove(&fullRepositoryName) {
+                entry.lastAccess = entry.lastAccess.max(previousEntry.lastAccess);
+            }
+            if recomputeUsage || entry.diskUsage == 0 {
+                entry.diskUsage = diskUsage(&clonePath);
+            }
+            self.entries.insert(fullRepositoryName, entry);
+        }
+        self.save();
+    }
+    pub fn isCloned(&self, fullRepositoryName: &str) -> bool {
+        self.clonesDirectory.join(fullRepositoryName).join(".git").exists()
+    }
+    pub fn materialize(&mut self, config: &MountConfig, storage: &Storage, fullRepositoryName: &str) -> Result<(), String> {
+        if self.isCloned(fullRepositoryName) {
+            self.touch(fullRepositoryName);
+            return Ok(());
+        }
+        clone::cloneRepository(config, storage, fullRepositoryName)?;
+        let clonePath = self.clonesDirectory.join(fullRepositoryName);
+        self.entries.insert(fullRepositoryName.to_string(), CloneEntry {
+            lastAccess: now(),
+            diskUsage: diskUsage(&clonePath),
+        });
+        self.save();
+        self.enforceQuota(fullRepositoryName);
+        Ok(())
+    }
+    pub fn touch(&mut self, fullRepositoryName: &str) {
+        let hasToPersist = match self.entries.get_mut(fullRepositoryName) {
+            Some(entry) => {
+                let currentTime = now();
+                let hasToPersist = currentTime > entry.lastAccess + ACCESS_PERSIST_INTERVAL;
+                entry.lastAccess = currentTime;
+                hasToPersist
+            },
+            None => false,
+        };
+        if hasToPersist {
+            self.save();
+        }
+    }
+    pub fn fetching(&self) -> Arc<Mutex<HashSet<String>>> {
+        self.fetching.clone()
+    }
+    // Opening a file counts as an access too.
+    pub fn opened(&mut self, fullRepositoryName: &str) {
+        *self.openFiles.entry(fullRepositoryName.to_string()).or_insert(0) += 1;
+        self.touch(fullRepositoryName);
+    }
+    pub fn released(&mut self, fullRepositoryName: &str) {
+        let isLast = match self.openFiles.get_mut(fullRepositoryName) {
+            Some(openFiles) => {
+                *openFiles -= 1;
+                *openFiles == 0
+            },
+            None => false,
+        };
+        if isLast {
+            self.openFiles.remove(fullRepositoryName);
+        }
+    }
+    // Evicts clean clones, least recently used first, until the store fits the quota.
+    // `keep` is the clone that is being used right now and is never evicted, nor are
+    // clones with files open through the mount or being fetched.
+    pub fn enforceQuota(&mut self, keep: &str) {
+        let quota = match self.quota {
+            Some(quota) => quota,
+            None => return,
+        };
+        self.refresh(true);
+        let mut totalUsage: u64 = self.entries.values().map(|entry| entry.diskUsage).sum();
+        if totalUsage <= quota {
+            return;
+        }
+        let mut candidates: Vec<(u64, String)> = self.entries
+            .iter()
+            .filter(|&(fullRepositoryName, _)| fullRepositoryName != keep && !self.openFiles.contains_key(fullRepositoryName))
+            .map(|(fullRepositoryName, entry)| (entry.lastAccess, fullRepositoryName.clone()))
+            .collect();
+        candidates.sort();
+        for (_, fullRepositoryName) in candidates {
+            if totalUsage <= quota {
+                break;
+            }
+            let clonePath = self.clonesDirectory.join(&fullRepositoryName);
+            if !self.fetching.lock().unwrap().insert(fullRepositoryName.clone()) {
+                continue;
+            }
+            let result = match hasNoLocalModifications(&clonePath) {
+                true => fs::remove_dir_all(&clonePath).map(|_| true),
+                false => Ok(false),
+            };
+            self.fetching.lock().unwrap().remove(&fullRepositoryName);
+            match result {
+                Ok(true) => (),
+                Ok(false) => continue,
+                Err(error) => {
+                    eprintln!("gh-mount: could not evict {}: {}", fullRepositoryName, error);
+                    continue;
+                },
+            }
+            // drop the owner directory too once its last clone is gone
+            if let Some(ownerPath) = clonePath.parent() {
+                let _ = fs::remove_dir(ownerPath);
+            }
+            let entry = self.entries.remove(&fullRepositoryName).unwrap();
+            totalUsage = totalUsage.saturating_sub(entry.diskUsage);
+            eprintln!("gh-mount: evicted clone of {} to stay below the storage quota", fullRepositoryName);
+        }
+        if totalUsage > quota {
+            eprintln!("gh-mount: clones use {} bytes, over the {} bytes quota, but the rest have local modifications", totalUsage, quota);
+        }
+        self.save();
+    }
+    fn save(&self) {
+        let mut contents = String::new();
+        for (fullRepositoryName, entry) in self.entries.iter() {
+            contents += &format!("{} {} {}\n", fullRepositoryName, entry.lastAccess, entry.diskUsage);
+        }
+        if let Err(error) = fs::write(&self.indexPath, contents) {
+            eprintln!("gh-mount: could not write {}: {}", self.indexPath.display(), error);
+        }
+    }
+}
+
+pub fn listClones(clonesDirectory: &Path) -> Vec<String> {
+    let mut clones = Vec::new();
+    let owners = match fs::read_dir(clonesDirectory) {
+        Ok(owners) => owners,
+        Err(_) => return clones,
+    };
+    for owner in owners.filter_map(|owner| owner.ok()) {
+        let repositories = match fs::read_dir(owner.path()) {
+            Ok(repositories) => repositories,
+            Err(_) => continue,
+        };
+        for repository in repositories.filter_map(|repository| repository.ok()) {
+            if !repository.path().join(".git").exists() {
+                continue;
+            }
+            clones.push(format!(
+                "{}/{}",
+                owner.file_name().to_string_lossy(),
+                repository.file_name().to_string_lossy()
+            ));
+        }
+    }
+    clones
+}
+
+// A clone can only be thrown away when everything in it can be fetched again: no
+// uncommitted or untracked files, no stashes and no commits missing from the remotes.
+fn hasNoLocalModifications(clonePath: &Path) -> bool {
+    let checks: [&[&str]; 3] = [
+        &["status", "--porcelain"],
+        &["stash", "list"],
+        &["log", "--branches", "--not", "--remotes", "--format=%H", "-n", "1"],
+    ];
+    for args in checks.iter() {
+        let output = Command::new("git").arg("-C").arg(clonePath).args(*args).output();
+        match output {
+            Ok(ref output) if output.status.success() && output.stdout.len() == 0 => (),
+            _ => return false,
+        }
+    }
+    true
+}
+
+fn diskUsage(path: &Path) -> u64 {
+    let metadata = match fs::symlink_metadata(path) {
+        Ok(metadata) => metadata,
+        Err(_) => return 0,
+    };
+    let mut usage = metadata.blocks() * 512;
+    if metadata.is_dir() {
+        if let Ok(children) = fs::read_dir(path) {
+            for child in children.filter_map(|child| child.ok()) {
+                usage += diskUsage(&child.path());
+            }
+        }
+    }
+    usage
+}
+
+fn modifiedAt(path: &Path) -> u64 {
+    fs::metadata(path)
+        .and_then(|metadata| metadata.modified())
+        .ok()
+        .and_then(|modified| modified.duration_since(UNIX_EPOCH).ok())
+        .map(|duration| duration.as_secs())
+        .unwrap_or(0)
+}
+
+fn now() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs()
+}
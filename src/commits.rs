@@ -0,0 +1,115 @@
+use fuser::FileType;
+use libc::{c_int, ENOENT};
+use time;
+
+use tree::{TreeRoot, Trees};
+use views::{ViewNode, ViewPath, Views};
+
+// `.commits/<date>/<sha>/`: the commits of the default branch, grouped by the UTC day
+// they were committed, with the `message`, `meta.json`, `diff` and `patch` of each
+// and `tree/` showing the repository at that commit.
+//
+// Only the days of the latest COMMITS_LISTED commits are listed, older days can
+// still be entered by name (`.commits/2021-03-14`).
+
+const COMMITS_LISTED: u32 = 100;
+const COMMIT_FILES: &[&str] = &["diff", "message", "meta.json", "patch", "tree"];
+
+fn isDate(name: &str) -> bool {
+    name.len() == 10 && time::strptime(name, "%Y-%m-%d").is_ok()
+}
+
+// Days of the latest COMMITS_LISTED commits, newest first. Listing every day would
+// mean paginating the whole history of the branch on each `ls`.
+fn listDates(views: &mut Views, fullRepositoryName: &str) -> Result<Vec<String>, c_int> {
+    let output = views.gh(&[
+        "api", &format!("repos/{}/commits?per_page={}", fullRepositoryName, COMMITS_LISTED),
+        "--jq", ".[].commit.committer.date[:10]",
+    ])?;
+    let mut dates: Vec<String> = Vec::new();
+    for date in output.lines() {
+        if date.len() > 0 && !dates.iter().any(|listed| listed == date) {
+            dates.push(date.to_string());
+        }
+    }
+    Ok(dates)
+}
+
+fn listCommits(views: &mut Views, fullRepositoryName: &str, date: &str) -> Result<Vec<String>, c_int> {
+    if !isDate(date) {
+        return Ok(Vec::new());
+    }
+    let output = views.gh(&[
+        "api", "--paginate",
+        &format!("repos/{}/commits?since={}T00:00:00Z&until={}T23:59:59Z&per_page=100", fullRepositoryName, date, date),
+        "--jq", ".[].sha",
+    ])?;
+    Ok(output.lines().filter(|sha| sha.len() > 0).map(|sha| sha.to_string()).collect())
+}
+
+// Whether `sha` is a commit made on `date`, without listing the commits of that day.
+fn isCommitOf(views: &mut Views, fullRepositoryName: &str, date: &str, sha: &str) -> Result<bool, c_int> {
+    if !isDate(date) || sha.len() != 40 || !sha.chars().all(|character| character.is_digit(16)) {
+        return Ok(false);
+    }
+    match views.gh(&[
+        "api", &format!("repos/{}/commits/{}", fullRepositoryName, sha), "--jq", "[.sha, .commit.committer.date[:10]] | @tsv",
+    ]) {
+        Ok(output) => Ok(output == format!("{}\t{}", sha, date)),
+        Err(ENOENT) => Ok(false),
+        Err(error) => Err(error),
+    }
+}
+
+pub fn resolve(views: &mut Views, trees: &mut Trees, path: &ViewPath) -> Result<Option<ViewNode>, c_int> {
+    let rest = &path.rest;
+    match rest.len() {
+        0 => return Ok(Some(ViewNode::Directory)),
+        1 => return Ok(if isDate(&rest[0]) { Some(ViewNode::Directory) } else { None }),
+        _ => (),
+    }
+    let sha = &rest[1];
+    let isKnown = rest.len() == 3 && trees.isRegistered(&path.fullPath);
+    if !isKnown && !isCommitOf(views, &path.repository, &rest[0], sha)? {
+        return Ok(None);
+    }
+    let commitPath = format!("repos/{}/commits/{}", path.repository, sha);
+    let node = match (rest.get(2).map(|name| name.as_str()), rest.len()) {
+        (None, _) => ViewNode::Directory,
+        (Some("message"), 3) => ViewNode::File(views.ghFile(&["api", &commitPath, "--jq", ".commit.message"])?),
+        (Some("meta.json"), 3) => ViewNode::File(views.ghFile(&["api", &commitPath])?),
+        (Some("diff"), 3) => ViewNode::File(views.ghFile(&["api", &commitPath, "-H", "Accept: application/vnd.github.diff"])?),
+        (Some("patch"), 3) => ViewNode::File(views.ghFile(&["api", &commitPath, "-H", "Accept: application/vnd.github.patch"])?),
+        (Some("tree"), 3) => {
+            // everything below is served by the tree of the commit
+            trees.registerRoot(&path.fullPath, TreeRoot {
+                repository: path.repository.clone(),
+                reference: sha.clone(),
+            });
+            ViewNode::Directory
+        },
+        _ => return Ok(None),
+    };
+    Ok(Some(node))
+}
+
+pub fn list(views: &mut Views, path: &ViewPath) -> Result<Vec<(String, FileType)>, c_int> {
+    match path.rest.as_slice() {
+        [] => Ok(listDates(views, &path.repository)?
+            .into_iter()
+            .map(|date| (date, FileType::Directory))
+            .collect()),
+        [ref date] if isDate(date) => Ok(listCommits(views, &path.repository, date)?
+            .into_iter()
+            .map(|sha| (sha, FileType::Directory))
+            .collect()),
+        [ref date, ref sha] if isCommitOf(views, &path.repository, date, sha)? => Ok(COMMIT_FILES
+            .iter()
+            .map(|name| {
+                let kind = if *name == "tree" { FileType::Directory } else { FileType::RegularFile };
+                (name.to_string(), kind)
+            })
+            .collect()),
+        _ => Err(ENOENT),
+    }
+}
@@ -0,0 +1,123 @@
+use fuser::FileType;
+use libc::{c_int, ENOENT};
+
+use tree::{TreeRoot, Trees};
+use views::{decodeName, pathChildren, ViewNode, ViewPath, Views};
+
+// `.compare/<base>...<head>/`: what `<head>` has that `<base>` doesn't, as a `diff`,
+// the `commits` (one per line) and `changed/` with the added and modified files at
+// `<head>`. Refs are branches, tags or commits, with `/` written `%2F`. Comparisons
+// can't be listed, only entered by name.
+
+const COMPARE_FILES: &[&str] = &["changed", "commits", "diff"];
+const COMMIT_TEMPLATE: &str = r#".commits[] | "\(.sha[:7]) \(.commit.committer.date[:10]) \(.commit.message | split("\n")[0]) (\(.commit.author.name))""#;
+
+// `<base>...<head>` -> (base, head)
+fn parseRange(name: &str) -> Option<(String, String)> {
+    let index = name.find("...")?;
+    let (base, head) = (&name[..index], &name[index + 3..]);
+    match base.len() > 0 && head.len() > 0 {
+        true => Some((decodeName(base), decodeName(head))),
+        false => None,
+    }
+}
+
+fn compareEndpoint(fullRepositoryName: &str, base: &str, head: &str) -> String {
+    format!("repos/{}/compare/{}...{}", fullRepositoryName, base, head)
+}
+
+// Paths of the files `head` adds or modifies. The API stops at 300 files.
+fn changedFiles(views: &mut Views, endpoint: &str) -> Result<Vec<String>, c_int> {
+    let output = views.gh(&[
+        "api", "--paginate", &format!("{}?per_page=100", endpoint),
+        "--jq", r#".files[]? | select(.status != "removed") | .filename"#,
+    ])?;
+    let mut files: Vec<String> = Vec::new();
+    for path in output.lines().filter(|path| path.len() > 0) {
+        if !files.iter().any(|file| file == path) {
+            files.push(path.to_string());
+        }
+    }
+    Ok(files)
+}
+
+pub fn resolve(views: &mut Views, trees: &mut Trees, path: &ViewPath) -> Result<Option<ViewNode>, c_int> {
+    let (base, head) = match path.rest.first() {
+        Some(name) => match parseRange(name) {
+            Some(range) => range,
+            None => return Ok(None),
+        },
+        None => return Ok(Some(ViewNode::Directory)),
+    };
+    let endpoint = compareEndpoint(&path.repository, &base, &head);
+    // the refs exist when the comparison does
+    let status = views.gh(&["api", &endpoint, "--jq", ".status"])?;
+    if status.len() == 0 {
+        return Ok(None);
+    }
+    let node = match (path.rest.get(1).map(|name| name.as_str()), path.rest.len()) {
+        (None, _) => ViewNode::Directory,
+        (Some("diff"), 2) => ViewNode::File(views.ghFile(&["api", &endpoint, "-H", "Accept: application/vnd.github.diff"])?),
+        (Some("commits"), 2) => ViewNode::File(views.ghFile(&["api", "--paginate", &format!("{}?per_page=100", endpoint), "--jq", COMMIT_TEMPLATE])?),
+        (Some("changed"), _) => {
+            let relativePath = path.rest[2..].join("/");
+            if relativePath.len() == 0 {
+                return Ok(Some(ViewNode::Directory));
+            }
+            let changedFiles = changedFiles(views, &endpoint)?;
+            if !changedFiles.contains(&relativePath) {
+                let isDirectory = changedFiles.iter().any(|changedFile| changedFile.starts_with(&(relativePath.clone() + "/")));
+                return Ok(if isDirectory { Some(ViewNode::Directory) } else { None });
+            }
+            let headRoot = TreeRoot {
+                repository: path.repository.clone(),
+                reference: head,
+            };
+            match trees.remoteEntry(&headRoot, &relativePath)? {
+                Some(entry) => ViewNode::Blob {
+                    repository: headRoot.repository,
+                    sha: entry.sha,
+                    size: entry.size,
+                },
+                None => return Ok(None),
+            }
+        },
+        _ => return Ok(None),
+    };
+    Ok(Some(node))
+}
+
+pub fn list(views: &mut Views, path: &ViewPath) -> Result<Vec<(String, FileType)>, c_int> {
+    let (base, head) = match path.rest.first() {
+        Some(name) => parseRange(name).ok_or(ENOENT)?,
+        None => return Ok(Vec::new()),
+    };
+    let endpoint = compareEndpoint(&path.repository, &base, &head);
+    match path.rest.get(1).map(|name| name.as_str()) {
+        None => Ok(COMPARE_FILES
+            .iter()
+            .map(|name| {
+                let kind = if *name == "changed" { FileType::Directory } else { FileType::RegularFile };
+                (name.to_string(), kind)
+            })
+            .collect()),
+        Some("changed") => Ok(pathChildren(&changedFiles(views, &endpoint)?, &path.rest[2..].join("/"))),
+        Some(_) => Err(ENOENT),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parsesRanges() {
+        assert_eq!(parseRange("main...feature"), Some(("main".to_string(), "feature".to_string())));
+        assert_eq!(parseRange("v1.0...feature%2Fx"), Some(("v1.0".to_string(), "feature/x".to_string())));
+        assert_eq!(parseRange("main....dots"), Some(("main".to_string(), ".dots".to_string())));
+        assert_eq!(parseRange("main..feature"), None);
+        assert_eq!(parseRange("...feature"), None);
+        assert_eq!(parseRange("main..."), None);
+        assert_eq!(parseRange("main"), None);
+    }
+}
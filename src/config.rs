@@ -0,0 +1,374 @@
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use attributes::Owner;
+use libc;
+
+#[derive(Clone, Default)]
+pub struct CloneStrategy {
+    pub depth: Option<u32>,
+    pub filter: Option<String>,
+    pub sparse: Option<Vec<String>>,
+}
+
+impl CloneStrategy {
+    fn merge(&mut self, other: &CloneStrategy) {
+        if other.depth.is_some() {
+            self.depth = other.depth;
+        }
+        if other.filter.is_some() {
+            self.filter = other.filter.clone();
+        }
+        if other.sparse.is_some() {
+            self.sparse = other.sparse.clone();
+        }
+    }
+    fn applySetting(&mut self, key: &str, value: &str) -> Result<bool, String> {
+        match key {
+            "clone_depth" => {
+                let depth = value.parse::<u32>()
+                    .map_err(|_| format!("clone_depth must be a number, got {:?}", value))?;
+                self.depth = if depth == 0 { None } else { Some(depth) };
+            },
+            "clone_filter" => {
+                self.filter = match value {
+                    "" | "none" => None,
+                    "blobless" => Some("blob:none".to_string()),
+                    "treeless" => Some("tree:0".to_string()),
+                    filter if filter.starts_with("blob:") || filter.starts_with("tree:") => Some(filter.to_string()),
+                    filter => return Err(format!("unknown clone_filter {:?}", filter)),
+                };
+            },
+            "clone_sparse" => {
+                let paths: Vec<String> = value
+                    .split(|character: char| character == ',' || character.is_whitespace())
+                    .filter(|path| path.len() > 0)
+                    .map(|path| path.to_string())
+                    .collect();
+                self.sparse = if paths.len() == 0 { None } else { Some(paths) };
+            },
+            _ => return Ok(false),
+        }
+        Ok(true)
+    }
+    // Flags handed to `git clone` (after the `--` of `gh repo clone`).
+    pub fn gitFlags(&self) -> Vec<String> {
+        let mut flags = Vec::new();
+        if let Some(depth) = self.depth {
+            flags.push(format!("--depth={}", depth));
+        }
+        if let Some(ref filter) = self.filter {
+            flags.push(format!("--filter={}", filter));
+        }
+        if self.sparse.is_some() {
+            flags.push("--sparse".to_string());
+        }
+        flags
+    }
+}
+
+pub struct MountConfig {
+    pub autoClone: Vec<String>,
+    pub cloneStrategy: CloneStrategy,
+    // `[owner/pattern]` sections of the config file, in file order
+    pub repositoryCloneStrategies: Vec<(String, CloneStrategy)>,
+    // `--clone-...` flags, which win over the sections
+    argumentCloneSettings: Vec<(String, String)>,
+    pub storageRoot: Option<PathBuf>,
+    pub storageQuota: Option<u64>,
+    // clones whose last fetch is older than this are fetched again when accessed
+    pub fetchAfter: Option<u64>,
+    // period of the background fetch of every stale clone
+    pub fetchSchedule: Option<u64>,
+    // `public`, `private` or `internal`, used by `mkdir owner/new-repo`
+    pub repositoryVisibility: String,
+    // files of remote trees get the time of the last commit touching them instead of
+    // the time of the commit the tree is shown at (one API call per file)
+    pub lastCommitTimes: bool,
+    // owner of the files of the mount, the mounting user by default
+    pub uid: Option<u32>,
+    pub gid: Option<u32>,
+}
+
+impl MountConfig {
+    pub fn new() -> MountConfig {
+        MountConfig {
+            autoClone: Vec::new(),
+            cloneStrategy: CloneStrategy::default(),
+            repositoryCloneStrategies: Vec::new(),
+            argumentCloneSettings: Vec::new(),
+            storageRoot: None,
+            storageQuota: None,
+            fetchAfter: None,
+            fetchSchedule: None,
+            repositoryVisibility: "private".to_string(),
+            lastCommitTimes: false,
+            uid: None,
+            gid: None,
+        }
+    }
+    pub fn load() -> MountConfig {
+        let mut config = MountConfig::new();
+        let contents = match fs::read_to_string(MountConfig::configFilePath()) {
+            Ok(contents) => contents,
+            Err(_) => return config,
+        };
+        let mut section: Option<String> = None;
+        for line in contents.lines() {
+            let line = line.trim();
+            if line.len() == 0 || line.starts_with("#") {
+                continue;
+            }
+            if line.starts_with("[") && line.ends_with("]") {
+                let pattern = line[1..line.len() - 1].trim().to_string();
+                config.repositoryCloneStrategies.push((pattern.clone(), CloneStrategy::default()));
+                section = Some(pattern);
+                continue;
+            }
+            let (key, value) = match line.find("=") {
+                Some(index) => (line[..index].trim(), line[index + 1..].trim()),
+                None => continue,
+            };
+            let result = match section {
+                None => config.applySetting(key, value),
+                Some(ref pattern) => {
+                    let strategy = &mut config.repositoryCloneStrategies.last_mut().unwrap().1;
+                    match strategy.applySetting(key, value) {
+                        Ok(false) => Err(format!("{:?} can not be set for [{}]", key, pattern)),
+                        Ok(true) => Ok(()),
+                        Err(error) => Err(error),
+                    }
+                },
+            };
+            if let Err(error) = result {
+                eprintln!("gh-mount: {}: {}", MountConfig::configFilePath().display(), error);
+            }
+        }
+        config
+    }
+    fn applySetting(&mut self, key: &str, value: &str) -> Result<(), String> {
+        if self.cloneStrategy.applySetting(key, value)? {
+            return Ok(());
+        }
+        match key {
+            "auto_clone" => {
+                for pattern in value.split_whitespace() {
+                    self.autoClone.push(pattern.to_string());
+                }
+            },
+            "storage_root" => {
+                self.storageRoot = if value.len() == 0 { None } else { Some(PathBuf::from(value)) };
+            },
+            "storage_quota" => {
+                self.storageQuota = match value {
+                    "" | "none" => None,
+                    _ => Some(parseSize(value).ok_or(format!("storage_quota must be a size like 20G, got {:?}", value))?),
+                };
+            },
+            "fetch_after" | "fetch_schedule" => {
+                let duration = match value {
+                    "" | "never" => None,
+                    _ => Some(parseDuration(value).ok_or(format!("{} must be a duration like 15m, got {:?}", key, value))?),
+                };
+                match key {
+                    "fetch_after" => self.fetchAfter = duration,
+                    _ => self.fetchSchedule = duration,
+                }
+            },
+            "repository_visibility" => match value {
+                "public" | "private" | "internal" => self.repositoryVisibility = value.to_string(),
+                _ => return Err(format!("repository_visibility must be public, private or internal, got {:?}", value)),
+            },
+            "file_times" => match value {
+                "ref" => self.lastCommitTimes = false,
+                "last_commit" => self.lastCommitTimes = true,
+                _ => return Err(format!("file_times must be ref or last_commit, got {:?}", value)),
+            },
+            "uid" | "gid" => {
+                let id = match value {
+                    "" => None,
+                    _ => Some(value.parse::<u32>().map_err(|_| format!("{} must be a number, got {:?}", key, value))?),
+                };
+                match key {
+                    "uid" => self.uid = id,
+                    _ => self.gid = id,
+                }
+            },
+            _ => return Err(format!("unknown config key {:?}", key)),
+        }
+        Ok(())
+    }
+    // Applies `--clone-depth 1` style flags on top of the config file and returns
+    // the remaining positional arguments.
+    pub fn applyArguments<I: Iterator<Item = String>>(&mut self, arguments: I) -> Result<Vec<String>, String> {
+        let mut positional = Vec::new();
+        let mut arguments = arguments;
+        while let Some(argument) = arguments.next() {
+            if !argument.starts_with("--") {
+                positional.push(argument);
+                continue;
+            }
+            let (key, value) = match argument.find("=") {
+                Some(index) => (argument[2..index].to_string(), argument[index + 1..].to_string()),
+                None => match arguments.next() {
+                    Some(value) => (argument[2..].to_string(), value),
+                    None => return Err(format!("missing value for {}", argument)),
+                },
+            };
+            let key = key.replace("-", "_");
+            self.applySetting(&key, &value)?;
+            if CloneStrategy::default().applySetting(&key, &value)? {
+                self.argumentCloneSettings.push((key, value));
+            }
+        }
+        Ok(positional)
+    }
+    pub fn configFilePath() -> PathBuf {
+        match xdgDirectory("XDG_CONFIG_HOME", ".config") {
+            Some(configHome) => configHome.join("gh_mount").join("config"),
+            None => PathBuf::from("gh_mount.config"),
+        }
+    }
+    pub fn owner(&self) -> Owner {
+        Owner {
+            uid: self.uid.unwrap_or_else(|| unsafe { libc::getuid() }),
+            gid: self.gid.unwrap_or_else(|| unsafe { libc::getgid() }),
+        }
+    }
+    pub fn shouldAutoClone(&self, fullRepositoryName: &str) -> bool {
+        self.autoClone
+            .iter()
+            .any(|pattern| matchesRepositoryPattern(pattern, fullRepositoryName))
+    }
+    // Mount wide settings first, then every matching `[pattern]` section on top, then
+    // the command line flags, so `--clone-depth 0` still clones a `[pattern]` fully.
+    pub fn cloneStrategyFor(&self, fullRepositoryName: &str) -> CloneStrategy {
+        let mut strategy = self.cloneStrategy.clone();
+        for (pattern, repositoryStrategy) in self.repositoryCloneStrategies.iter() {
+            if matchesRepositoryPattern(pattern, fullRepositoryName) {
+                strategy.merge(repositoryStrategy);
+            }
+        }
+        for &(ref key, ref value) in self.argumentCloneSettings.iter() {
+            // already checked by applyArguments
+            let _ = strategy.applySetting(key, value);
+        }
+        strategy
+    }
+}
+
+// `1024`, `500M`, `20G`... in bytes, with binary multiples.
+pub fn parseSize(value: &str) -> Option<u64> {
+    let value = value.trim();
+    let (number, multiplier) = match value.chars().last() {
+        Some('K') | Some('k') => (&value[..value.len() - 1], 1 << 10),
+        Some('M') | Some('m') => (&value[..value.len() - 1], 1 << 20),
+        Some('G') | Some('g') => (&value[..value.len() - 1], 1 << 30),
+        Some('T') | Some('t') => (&value[..value.len() - 1], 1 << 40),
+        _ => (value, 1),
+    };
+    number.trim().parse::<u64>().ok().and_then(|number| number.checked_mul(multiplier))
+}
+
+// `90`, `30s`, `15m`, `6h`, `1d`... in seconds.
+pub fn parseDuration(value: &str) -> Option<u64> {
+    let value = value.trim();
+    let (number, multiplier) = match value.chars().last() {
+        Some('s') => (&value[..value.len() - 1], 1),
+        Some('m') => (&value[..value.len() - 1], 60),
+        Some('h') => (&value[..value.len() - 1], 60 * 60),
+        Some('d') => (&value[..value.len() - 1], 24 * 60 * 60),
+        _ => (value, 1),
+    };
+    number.trim().parse::<u64>().ok().and_then(|number| number.checked_mul(multiplier))
+}
+
+// `$XDG_..._HOME` when set, otherwise the spec's default below the home directory.
+pub fn xdgDirectory(variable: &str, homeFallback: &str) -> Option<PathBuf> {
+    match env::var(variable) {
+        Ok(ref path) if path.len() > 0 && Path::new(path).is_absolute() => Some(PathBuf::from(path)),
+        _ => env::home_dir().map(|home| home.join(homeFallback)),
+    }
+}
+
+// Patterns are plain `owner/repo` names where `*` matches any run of characters,
+// so `victorlpgazolli/*` selects every repository of that account.
+pub fn matchesRepositoryPattern(pattern: &str, fullRepositoryName: &str) -> bool {
+    let pattern = pattern.as_bytes();
+    let name = fullRepositoryName.as_bytes();
+    let (mut patternIndex, mut nameIndex) = (0, 0);
+    let mut lastStar: Option<(usize, usize)> = None;
+    while nameIndex < name.len() {
+        if patternIndex < pattern.len() && pattern[patternIndex] == b'*' {
+            lastStar = Some((patternIndex, nameIndex));
+            patternIndex += 1;
+        } else if patternIndex < pattern.len() && pattern[patternIndex] == name[nameIndex] {
+            patternIndex += 1;
+            nameIndex += 1;
+        } else if let Some((starIndex, starName)) = lastStar {
+            patternIndex = starIndex + 1;
+            nameIndex = starName + 1;
+            lastStar = Some((starIndex, starName + 1));
+        } else {
+            return false;
+        }
+    }
+    while patternIndex < pattern.len() && pattern[patternIndex] == b'*' {
+        patternIndex += 1;
+    }
+    patternIndex == pattern.len()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn matchesRepositoryPatterns() {
+        assert!(matchesRepositoryPattern("cli/cli", "cli/cli"));
+        assert!(!matchesRepositoryPattern("cli/cli", "cli/cli2"));
+        assert!(matchesRepositoryPattern("cli/*", "cli/go-gh"));
+        assert!(!matchesRepositoryPattern("cli/*", "torvalds/linux"));
+        assert!(matchesRepositoryPattern("*/linux", "torvalds/linux"));
+        assert!(matchesRepositoryPattern("*", "torvalds/linux"));
+        assert!(matchesRepositoryPattern("org/*-docs", "org/api-v2-docs"));
+        assert!(!matchesRepositoryPattern("org/*-docs", "org/api-docs-old"));
+        assert!(matchesRepositoryPattern("org/a*b*c", "org/aXbYbZc"));
+    }
+
+    #[test]
+    fn parsesSizes() {
+        assert_eq!(parseSize("1024"), Some(1024));
+        assert_eq!(parseSize("500M"), Some(500 << 20));
+        assert_eq!(parseSize(" 20g "), Some(20 << 30));
+        assert_eq!(parseSize("2T"), Some(2 << 40));
+        assert_eq!(parseSize("20000000T"), None);
+        assert_eq!(parseSize("G"), None);
+        assert_eq!(parseSize("1.5G"), None);
+        assert_eq!(parseSize("-1K"), None);
+    }
+
+    #[test]
+    fn parsesDurations() {
+        assert_eq!(parseDuration("90"), Some(90));
+        assert_eq!(parseDuration("30s"), Some(30));
+        assert_eq!(parseDuration("15m"), Some(15 * 60));
+        assert_eq!(parseDuration("6h"), Some(6 * 60 * 60));
+        assert_eq!(parseDuration("1d"), Some(24 * 60 * 60));
+        assert_eq!(parseDuration("99999999999999999d"), None);
+        assert_eq!(parseDuration("1w"), None);
+        assert_eq!(parseDuration(""), None);
+    }
+
+    #[test]
+    fn cloneFlagsWinOverSections() {
+        let mut config = MountConfig::new();
+        let mut section = CloneStrategy::default();
+        section.depth = Some(1);
+        config.repositoryCloneStrategies.push(("torvalds/*".to_string(), section));
+        config.applyArguments(vec!["--clone-depth".to_string(), "0".to_string()].into_iter()).unwrap();
+        assert_eq!(config.cloneStrategyFor("torvalds/linux").depth, None);
+        assert_eq!(MountConfig::new().cloneStrategyFor("torvalds/linux").depth, None);
+    }
+}
@@ -0,0 +1,114 @@
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, SystemTime};
+
+use clone_store;
+use config::MountConfig;
+
+// Brings clones up to date with `git fetch` on background threads, so a stale clone
+// never blocks the lookup that noticed it. Failures are only reported.
+#[derive(Clone)]
+pub struct Fetcher {
+    clonesDirectory: PathBuf,
+    fetchAfter: Option<u64>,
+    inFlight: Arc<Mutex<HashSet<String>>>,
+}
+
+impl Fetcher {
+    // `inFlight` is shared with the CloneStore, which never evicts a clone in it.
+    pub fn new(config: &MountConfig, clonesDirectory: &Path, inFlight: Arc<Mutex<HashSet<String>>>) -> Fetcher {
+        Fetcher {
+            clonesDirectory: clonesDirectory.to_path_buf(),
+            fetchAfter: config.fetchAfter,
+            inFlight: inFlight,
+        }
+    }
+    pub fn fetchIfStale(&self, fullRepositoryName: &str) {
+        let fetchAfter = match self.fetchAfter {
+            Some(fetchAfter) => fetchAfter,
+            None => return,
+        };
+        let clonePath = self.clonesDirectory.join(fullRepositoryName);
+        if !isStale(&clonePath, fetchAfter) {
+            return;
+        }
+        if !self.inFlight.lock().unwrap().insert(fullRepositoryName.to_string()) {
+            return;
+        }
+        let inFlight = self.inFlight.clone();
+        let fullRepositoryName = fullRepositoryName.to_string();
+        thread::spawn(move || {
+            if let Err(error) = fetchClone(&clonePath) {
+                eprintln!("gh-mount: background fetch of {} failed: {}", fullRepositoryName, error);
+            }
+            inFlight.lock().unwrap().remove(&fullRepositoryName);
+        });
+    }
+    // Every `period` seconds, fetches every clone that is stale by then.
+    pub fn startSchedule(&self, period: u64) {
+        let fetcher = self.clone();
+        thread::spawn(move || loop {
+            thread::sleep(Duration::from_secs(period));
+            for fullRepositoryName in clone_store::listClones(&fetcher.clonesDirectory) {
+                let clonePath = fetcher.clonesDirectory.join(&fullRepositoryName);
+                if !isStale(&clonePath, fetcher.fetchAfter.unwrap_or(period)) {
+                    continue;
+                }
+                if !fetcher.inFlight.lock().unwrap().insert(fullRepositoryName.clone()) {
+                    continue;
+                }
+                if let Err(error) = fetchClone(&clonePath) {
+                    eprintln!("gh-mount: scheduled fetch of {} failed: {}", fullRepositoryName, error);
+                }
+                fetcher.inFlight.lock().unwrap().remove(&fullRepositoryName);
+            }
+        });
+    }
+}
+
+// git touches FETCH_HEAD on every fetch, the clone itself counts as the first one.
+fn isStale(clonePath: &Path, fetchAfter: u64) -> bool {
+    let gitDirectory = clonePath.join(".git");
+    let lastFetch = fs::metadata(gitDirectory.join("FETCH_HEAD"))
+        .or_else(|_| fs::metadata(&gitDirectory))
+        .and_then(|metadata| metadata.modified());
+    match lastFetch.ok().and_then(|lastFetch| SystemTime::now().duration_since(lastFetch).ok()) {
+        Some(age) => age.as_secs() >= fetchAfter,
+        None => false,
+    }
+}
+
+// Fetches, then fast-forwards the checkout when it is the default branch and has no
+// local changes; any other checkout is left for its owner to update.
+fn fetchClone(clonePath: &Path) -> Result<(), String> {
+    git(clonePath, &["fetch", "--prune", "--quiet"])?;
+    let currentBranch = match git(clonePath, &["symbolic-ref", "--quiet", "--short", "HEAD"]) {
+        Ok(currentBranch) => currentBranch,
+        Err(_) => return Ok(()), // detached HEAD
+    };
+    let defaultBranch = git(clonePath, &["symbolic-ref", "--quiet", "--short", "refs/remotes/origin/HEAD"])?;
+    if defaultBranch != format!("origin/{}", currentBranch) {
+        return Ok(());
+    }
+    if git(clonePath, &["status", "--porcelain"])?.len() > 0 {
+        return Ok(());
+    }
+    git(clonePath, &["merge", "--ff-only", "--quiet", &defaultBranch]).map(|_| ())
+}
+
+pub fn git(clonePath: &Path, args: &[&str]) -> Result<String, String> {
+    let output = Command::new("git")
+        .arg("-C")
+        .arg(clonePath)
+        .args(args)
+        .output()
+        .map_err(|error| format!("could not run git: {}", error))?;
+    match output.status.success() {
+        true => Ok(String::from_utf8_lossy(&output.stdout).trim().to_string()),
+        false => Err(format!("git {}: {}", args.join(" "), String::from_utf8_lossy(&output.stderr).trim())),
+    }
+}
@@ -0,0 +1,133 @@
+use fuser::FileType;
+use libc::{c_int, ENOENT};
+
+use views::{ViewNode, ViewPath, Views};
+
+// `owner/.gists/<id>/`: the files of a gist, its `description` and `.revisions/<sha>/`
+// with the files as they were at every revision. Like repository files, gist files
+// report their size from the API and are downloaded when read, once per content.
+
+const GIST_FILES: &[&str] = &[".revisions", "description"];
+
+struct GistFile {
+    name: String,
+    size: u64,
+    rawUrl: String,
+}
+
+// The account's own gists include the secret ones.
+fn listGists(views: &mut Views, account: &str) -> Result<Vec<String>, c_int> {
+    let login = views.gh(&["api", "user", "--jq", ".login"])?;
+    let endpoint = match login == account {
+        true => "gists?per_page=100".to_string(),
+        false => format!("users/{}/gists?per_page=100", account),
+    };
+    let output = views.gh(&["api", "--paginate", &endpoint, "--jq", ".[].id"])?;
+    Ok(output.lines().filter(|id| id.len() > 0).map(|id| id.to_string()).collect())
+}
+
+// `gists/<id>` or `gists/<id>/<sha>` for a revision.
+fn listFiles(views: &mut Views, gistPath: &str) -> Result<Vec<GistFile>, c_int> {
+    let output = views.gh(&["api", gistPath, "--jq", ".files[] | [.filename, .size, .raw_url] | @tsv"])?;
+    let mut files = Vec::new();
+    for line in output.lines() {
+        let fields: Vec<&str> = line.split("\t").collect();
+        if fields.len() != 3 {
+            continue;
+        }
+        files.push(GistFile {
+            name: fields[0].to_string(),
+            size: fields[1].parse::<u64>().unwrap_or(0),
+            rawUrl: fields[2].to_string(),
+        });
+    }
+    Ok(files)
+}
+
+fn listRevisions(views: &mut Views, id: &str) -> Result<Vec<String>, c_int> {
+    let output = views.gh(&["api", &format!("gists/{}", id), "--jq", ".history[].version"])?;
+    Ok(output.lines().filter(|sha| sha.len() > 0).map(|sha| sha.to_string()).collect())
+}
+
+// Raw URLs end with `/raw/<sha of the contents>/<file name>`.
+fn fileNode(file: GistFile) -> ViewNode {
+    let contentSha = file.rawUrl.rsplit("/").nth(1).unwrap_or("").to_string();
+    ViewNode::Download {
+        endpoint: file.rawUrl,
+        fileName: format!("gist-{}", contentSha),
+        size: file.size,
+    }
+}
+
+// The API path of the gist directory `rest` (relative to `.gists`) shows: `<id>` or
+// `<id>/.revisions/<sha>`, with the number of path parts it takes.
+fn gistPathIn(views: &mut Views, account: &str, rest: &[String]) -> Result<Option<(String, usize)>, c_int> {
+    if rest.len() == 0 || !listGists(views, account)?.contains(&rest[0]) {
+        return Ok(None);
+    }
+    let id = &rest[0];
+    if rest.len() < 3 || rest[1] != ".revisions" {
+        return Ok(Some((format!("gists/{}", id), 1)));
+    }
+    match listRevisions(views, id)?.contains(&rest[2]) {
+        true => Ok(Some((format!("gists/{}/{}", id, rest[2]), 3))),
+        false => Ok(None),
+    }
+}
+
+pub fn resolve(views: &mut Views, path: &ViewPath) -> Result<Option<ViewNode>, c_int> {
+    if path.rest.len() == 0 {
+        return Ok(Some(ViewNode::Directory));
+    }
+    let (gistPath, length) = match gistPathIn(views, &path.account, &path.rest)? {
+        Some(gistPath) => gistPath,
+        None => return Ok(None),
+    };
+    let rest = &path.rest[length..];
+    let node = match rest {
+        [] => ViewNode::Directory,
+        [ref name] if length == 1 && name == ".revisions" => ViewNode::Directory,
+        [ref name] if length == 1 && name == "description" => {
+            let mut description = views.gh(&["api", &gistPath, "--jq", r#".description // """#])?;
+            description.push('\n');
+            ViewNode::File(description.into_bytes())
+        },
+        [ref name] => match listFiles(views, &gistPath)?.into_iter().find(|file| file.name == *name) {
+            Some(file) => fileNode(file),
+            None => return Ok(None),
+        },
+        _ => return Ok(None),
+    };
+    Ok(Some(node))
+}
+
+pub fn list(views: &mut Views, path: &ViewPath) -> Result<Vec<(String, FileType)>, c_int> {
+    if path.rest.len() == 0 {
+        return Ok(listGists(views, &path.account)?
+            .into_iter()
+            .map(|id| (id, FileType::Directory))
+            .collect());
+    }
+    let (gistPath, length) = gistPathIn(views, &path.account, &path.rest)?.ok_or(ENOENT)?;
+    let mut children = Vec::new();
+    match &path.rest[length..] {
+        [] => {
+            if length == 1 {
+                for name in GIST_FILES {
+                    let kind = if *name == ".revisions" { FileType::Directory } else { FileType::RegularFile };
+                    children.push((name.to_string(), kind));
+                }
+            }
+            for file in listFiles(views, &gistPath)? {
+                children.push((file.name, FileType::RegularFile));
+            }
+        },
+        [ref name] if length == 1 && name == ".revisions" => {
+            for sha in listRevisions(views, &path.rest[0])? {
+                children.push((sha, FileType::Directory));
+            }
+        },
+        _ => return Err(ENOENT),
+    }
+    Ok(children)
+}
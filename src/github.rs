@@ -0,0 +1,418 @@
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use time;
+
+// Thin wrappers around `gh api`, every call to GitHub goes through the gh CLI so the
+// mount uses whatever account `gh auth` is logged into.
+
+#[derive(Clone)]
+pub struct TreeEntry {
+    pub name: String,
+    // `blob`, `tree` or `commit` (submodules)
+    pub kind: String,
+    pub mode: String,
+    pub sha: String,
+    pub size: u64,
+}
+
+pub fn gh(args: &[&str]) -> Result<Vec<u8>, String> {
+    let output = Command::new("gh")
+        .args(args)
+        .output()
+        .map_err(|error| format!("could not run gh: {}", error))?;
+    match output.status.success() {
+        true => Ok(output.stdout),
+        false => Err(format!("gh {}: {}", args.join(" "), String::from_utf8_lossy(&output.stderr).trim())),
+    }
+}
+
+// `gh api ... --input -` with `input` as the request body.
+pub fn ghWithInput(args: &[&str], input: &[u8]) -> Result<Vec<u8>, String> {
+    let mut child = Command::new("gh")
+        .args(args)
+        .args(&["--input", "-"])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|error| format!("could not run gh: {}", error))?;
+    child.stdin.take().unwrap().write_all(input)
+        .map_err(|error| format!("could not write to gh: {}", error))?;
+    let output = child.wait_with_output()
+        .map_err(|error| format!("could not run gh: {}", error))?;
+    match output.status.success() {
+        true => Ok(output.stdout),
+        false => Err(format!("gh {}: {}", args.join(" "), String::from_utf8_lossy(&output.stderr).trim())),
+    }
+}
+
+pub fn isNotFound(error: &str) -> bool {
+    error.contains("HTTP 404")
+}
+
+pub fn isForbidden(error: &str) -> bool {
+    error.contains("HTTP 403")
+}
+
+pub fn ghText(args: &[&str]) -> Result<String, String> {
+    gh(args).map(|stdout| String::from_utf8_lossy(&stdout).trim_end().to_string())
+}
+
+pub fn defaultBranch(fullRepositoryName: &str) -> Result<String, String> {
+    ghText(&["api", &format!("repos/{}", fullRepositoryName), "--jq", ".default_branch"])
+}
+
+// (commit sha, tree sha, commit time) a branch, tag or commit points to.
+pub fn resolveCommit(fullRepositoryName: &str, reference: &str) -> Result<(String, String, SystemTime), String> {
+    let output = ghText(&[
+        "api", &format!("repos/{}/commits/{}", fullRepositoryName, reference),
+        "--jq", "[.sha, .commit.tree.sha, .commit.committer.date] | @tsv",
+    ])?;
+    let fields: Vec<&str> = output.split("\t").collect();
+    match fields.as_slice() {
+        [commitSha, treeSha, date] => Ok((commitSha.to_string(), treeSha.to_string(), parseTime(date).unwrap_or(UNIX_EPOCH))),
+        _ => Err(format!("could not resolve {} in {}", reference, fullRepositoryName)),
+    }
+}
+
+// Time of the last commit touching `relativePath` up to `commitSha`, None when the
+// history has no such commit.
+pub fn lastCommitTime(fullRepositoryName: &str, commitSha: &str, relativePath: &str) -> Result<Option<SystemTime>, String> {
+    let output = ghText(&[
+        "api", &format!("repos/{}/commits?sha={}&path={}&per_page=1", fullRepositoryName, commitSha, queryEncode(relativePath)),
+        "--jq", ".[0].commit.committer.date // empty",
+    ])?;
+    Ok(parseTime(&output))
+}
+
+// The time an account was created.
+pub fn accountCreationTime(account: &str) -> Result<Option<SystemTime>, String> {
+    let output = ghText(&["api", &format!("users/{}", account), "--jq", ".created_at"])?;
+    Ok(parseTime(&output))
+}
+
+// API timestamps, `2024-01-31T12:00:00Z`.
+pub fn parseTime(value: &str) -> Option<SystemTime> {
+    let timespec = time::strptime(value, "%Y-%m-%dT%H:%M:%SZ").ok()?.to_timespec();
+    match timespec.sec >= 0 {
+        true => Some(UNIX_EPOCH + Duration::from_secs(timespec.sec as u64)),
+        false => None,
+    }
+}
+
+pub fn listTree(fullRepositoryName: &str, treeSha: &str) -> Result<Vec<TreeEntry>, String> {
+    let output = ghText(&[
+        "api", &format!("repos/{}/git/trees/{}", fullRepositoryName, treeSha),
+        "--jq", ".tree[] | [.path, .type, .mode, .sha, (.size // 0 | tostring)] | @tsv",
+    ])?;
+    let mut entries = Vec::new();
+    for line in output.lines() {
+        let fields: Vec<&str> = line.split("\t").collect();
+        if fields.len() != 5 {
+            continue;
+        }
+        entries.push(TreeEntry {
+            name: fields[0].to_string(),
+            kind: fields[1].to_string(),
+            mode: fields[2].to_string(),
+            sha: fields[3].to_string(),
+            size: fields[4].parse::<u64>().unwrap_or(0),
+        });
+    }
+    Ok(entries)
+}
+
+// Blobs are content addressed, so once downloaded they are kept in the blob cache
+// and shared by every repository and ref.
+pub fn fetchBlob(fullRepositoryName: &str, blobSha: &str, blobsDirectory: &Path) -> Result<PathBuf, String> {
+    let blobPath = blobsDirectory.join(blobSha);
+    if blobPath.exists() {
+        return Ok(blobPath);
+    }
+    let contents = gh(&[
+        "api", &format!("repos/{}/git/blobs/{}", fullRepositoryName, blobSha),
+        "-H", "Accept: application/vnd.github.raw+json",
+    ])?;
+    let temporaryPath = blobsDirectory.join(format!("{}.tmp", blobSha));
+    fs::write(&temporaryPath, &contents)
+        .and_then(|_| fs::rename(&temporaryPath, &blobPath))
+        .map_err(|error| format!("could not cache blob {}: {}", blobSha, error))?;
+    Ok(blobPath)
+}
+
+// Streams the binary response of `endpoint` (a release asset, a job log, an artifact
+// archive) to `downloadPath`, through a temporary file so a failed download never
+// looks complete.
+pub fn download(endpoint: &str, downloadPath: &Path) -> Result<(), String> {
+    let temporaryPath = downloadPath.with_extension("tmp");
+    let file = fs::File::create(&temporaryPath)
+        .map_err(|error| format!("could not create {}: {}", temporaryPath.display(), error))?;
+    let args = ["api", endpoint, "-H", "Accept: application/octet-stream"];
+    let output = Command::new("gh")
+        .args(&args)
+        .stdout(Stdio::from(file))
+        .output()
+        .map_err(|error| format!("could not run gh: {}", error))?;
+    if !output.status.success() {
+        let _ = fs::remove_file(&temporaryPath);
+        return Err(format!("gh {}: {}", args.join(" "), String::from_utf8_lossy(&output.stderr).trim()));
+    }
+    fs::rename(&temporaryPath, downloadPath).map_err(|error| format!("could not cache {}: {}", endpoint, error))
+}
+
+// Every entry below the tree, named with its path relative to it.
+pub fn listTreeRecursive(fullRepositoryName: &str, treeSha: &str) -> Result<Vec<TreeEntry>, String> {
+    let output = ghText(&[
+        "api", &format!("repos/{}/git/trees/{}?recursive=1", fullRepositoryName, treeSha),
+        "--jq", ".tree[] | [.path, .type, .mode, .sha, .size // 0] | @tsv",
+    ])?;
+    Ok(output
+        .lines()
+        .filter_map(|line| {
+            let fields: Vec<&str> = line.split("\t").collect();
+            match fields.as_slice() {
+                [path, kind, mode, sha, size] => Some(TreeEntry {
+                    name: path.to_string(),
+                    kind: kind.to_string(),
+                    mode: mode.to_string(),
+                    sha: sha.to_string(),
+                    size: size.parse::<u64>().unwrap_or(0),
+                }),
+                _ => None,
+            }
+        })
+        .collect())
+}
+
+pub struct NewTreeEntry {
+    pub path: String,
+    pub mode: String,
+    pub kind: String,
+    // None deletes the path from the base tree
+    pub sha: Option<String>,
+}
+
+pub fn createBlob(fullRepositoryName: &str, contents: &[u8]) -> Result<String, String> {
+    let body = format!("{{\"content\":\"{}\",\"encoding\":\"base64\"}}", base64Encode(contents));
+    let output = ghWithInput(
+        &["api", "-X", "POST", &format!("repos/{}/git/blobs", fullRepositoryName), "--jq", ".sha"],
+        body.as_bytes(),
+    )?;
+    Ok(String::from_utf8_lossy(&output).trim().to_string())
+}
+
+pub fn createTree(fullRepositoryName: &str, baseTree: &str, entries: &[NewTreeEntry]) -> Result<String, String> {
+    let entries: Vec<String> = entries
+        .iter()
+        .map(|entry| format!(
+            "{{\"path\":{},\"mode\":{},\"type\":{},\"sha\":{}}}",
+            jsonString(&entry.path),
+            jsonString(&entry.mode),
+            jsonString(&entry.kind),
+            match entry.sha {
+                Some(ref sha) => jsonString(sha),
+                None => "null".to_string(),
+            }
+        ))
+        .collect();
+    let body = format!("{{\"base_tree\":{},\"tree\":[{}]}}", jsonString(baseTree), entries.join(","));
+    let output = ghWithInput(
+        &["api", "-X", "POST", &format!("repos/{}/git/trees", fullRepositoryName), "--jq", ".sha"],
+        body.as_bytes(),
+    )?;
+    Ok(String::from_utf8_lossy(&output).trim().to_string())
+}
+
+pub fn createCommit(fullRepositoryName: &str, message: &str, treeSha: &str, parentSha: &str) -> Result<String, String> {
+    let body = format!(
+        "{{\"message\":{},\"tree\":{},\"parents\":[{}]}}",
+        jsonString(message),
+        jsonString(treeSha),
+        jsonString(parentSha)
+    );
+    let output = ghWithInput(
+        &["api", "-X", "POST", &format!("repos/{}/git/commits", fullRepositoryName), "--jq", ".sha"],
+        body.as_bytes(),
+    )?;
+    Ok(String::from_utf8_lossy(&output).trim().to_string())
+}
+
+// Commit a branch points to, None when there is no such branch.
+pub fn branchHead(fullRepositoryName: &str, branch: &str) -> Result<Option<String>, String> {
+    match ghText(&["api", &format!("repos/{}/git/ref/heads/{}", fullRepositoryName, branch), "--jq", ".object.sha"]) {
+        Ok(commitSha) => Ok(Some(commitSha)),
+        Err(ref error) if isNotFound(error) => Ok(None),
+        Err(error) => Err(error),
+    }
+}
+
+pub fn createBranch(fullRepositoryName: &str, branch: &str, commitSha: &str) -> Result<(), String> {
+    createReference(fullRepositoryName, &format!("refs/heads/{}", branch), commitSha)
+}
+
+// `reference` is the full name, e.g. `refs/tags/v1.0`.
+pub fn createReference(fullRepositoryName: &str, reference: &str, commitSha: &str) -> Result<(), String> {
+    ghText(&[
+        "api", "-X", "POST", &format!("repos/{}/git/refs", fullRepositoryName),
+        "-f", &format!("ref={}", reference), "-f", &format!("sha={}", commitSha),
+    ]).map(|_| ())
+}
+
+// `reference` without the `refs/` prefix, e.g. `heads/main`.
+pub fn deleteReference(fullRepositoryName: &str, reference: &str) -> Result<(), String> {
+    ghText(&["api", "-X", "DELETE", &format!("repos/{}/git/refs/{}", fullRepositoryName, reference)]).map(|_| ())
+}
+
+// Fast-forward only, GitHub refuses the update when the branch is not an ancestor.
+pub fn updateBranch(fullRepositoryName: &str, branch: &str, commitSha: &str) -> Result<(), String> {
+    ghText(&[
+        "api", "-X", "PATCH", &format!("repos/{}/git/refs/heads/{}", fullRepositoryName, branch),
+        "-f", &format!("sha={}", commitSha), "-F", "force=false",
+    ]).map(|_| ())
+}
+
+// Creates an empty repository under the account gh is logged into, or under one of
+// its organizations.
+pub fn createRepository(owner: &str, name: &str, visibility: &str) -> Result<(), String> {
+    let login = ghText(&["api", "user", "--jq", ".login"])?;
+    let endpoint = match login == owner {
+        true => "user/repos".to_string(),
+        false => format!("orgs/{}/repos", owner),
+    };
+    ghText(&[
+        "api", "-X", "POST", &endpoint,
+        "-f", &format!("name={}", name), "-f", &format!("visibility={}", visibility),
+    ]).map(|_| ())
+}
+
+// Opens an issue and returns its number.
+pub fn createIssue(fullRepositoryName: &str, title: &str, body: &str, labels: &[String], assignees: &[String]) -> Result<u64, String> {
+    let jsonList = |values: &[String]| values.iter().map(|value| jsonString(value)).collect::<Vec<String>>().join(",");
+    let body = format!(
+        "{{\"title\":{},\"body\":{},\"labels\":[{}],\"assignees\":[{}]}}",
+        jsonString(title),
+        jsonString(body),
+        jsonList(labels),
+        jsonList(assignees)
+    );
+    let output = ghWithInput(
+        &["api", "-X", "POST", &format!("repos/{}/issues", fullRepositoryName), "--jq", ".number"],
+        body.as_bytes(),
+    )?;
+    let number = String::from_utf8_lossy(&output).trim().to_string();
+    number.parse::<u64>().map_err(|_| format!("unexpected issue number {:?}", number))
+}
+
+pub fn createIssueComment(fullRepositoryName: &str, number: u64, body: &str) -> Result<(), String> {
+    let body = format!("{{\"body\":{}}}", jsonString(body));
+    ghWithInput(
+        &["api", "-X", "POST", &format!("repos/{}/issues/{}/comments", fullRepositoryName, number), "--jq", ".id"],
+        body.as_bytes(),
+    ).map(|_| ())
+}
+
+// Creates a draft release for `tag` (the tag itself is only created when the release
+// is published) and returns its id.
+pub fn createDraftRelease(fullRepositoryName: &str, tag: &str) -> Result<String, String> {
+    let body = format!("{{\"tag_name\":{},\"name\":{},\"draft\":true}}", jsonString(tag), jsonString(tag));
+    let output = ghWithInput(
+        &["api", "-X", "POST", &format!("repos/{}/releases", fullRepositoryName), "--jq", ".id"],
+        body.as_bytes(),
+    )?;
+    Ok(String::from_utf8_lossy(&output).trim().to_string())
+}
+
+// Assets go to uploads.github.com instead of the API host, streamed by gh from the
+// file buffering them.
+pub fn uploadAsset(fullRepositoryName: &str, releaseId: &str, name: &str, inputPath: &Path) -> Result<(), String> {
+    let endpoint = format!(
+        "https://uploads.github.com/repos/{}/releases/{}/assets?name={}",
+        fullRepositoryName, releaseId, queryEncode(name)
+    );
+    ghText(&[
+        "api", "-X", "POST", &endpoint, "-H", "Content-Type: application/octet-stream",
+        "--input", &inputPath.display().to_string(), "--jq", ".id",
+    ]).map(|_| ())
+}
+
+// Percent-encodes a value for the query string of an API path.
+pub fn queryEncode(value: &str) -> String {
+    let mut encoded = String::with_capacity(value.len());
+    for byte in value.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => encoded.push(byte as char),
+            _ => encoded.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    encoded
+}
+
+pub fn jsonString(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len() + 2);
+    escaped.push('"');
+    for character in value.chars() {
+        match character {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            '\t' => escaped.push_str("\\t"),
+            character if (character as u32) < 0x20 => escaped.push_str(&format!("\\u{:04x}", character as u32)),
+            character => escaped.push(character),
+        }
+    }
+    escaped.push('"');
+    escaped
+}
+
+pub fn base64Encode(data: &[u8]) -> String {
+    const ALPHABET: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut encoded = String::with_capacity((data.len() + 2) / 3 * 4);
+    for chunk in data.chunks(3) {
+        let bytes = [chunk[0], *chunk.get(1).unwrap_or(&0), *chunk.get(2).unwrap_or(&0)];
+        let group = (bytes[0] as u32) << 16 | (bytes[1] as u32) << 8 | bytes[2] as u32;
+        for index in 0..4 {
+            if index <= chunk.len() {
+                encoded.push(ALPHABET[(group >> (18 - 6 * index) & 0x3f) as usize] as char);
+            } else {
+                encoded.push('=');
+            }
+        }
+    }
+    encoded
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn encodesQueryValues() {
+        assert_eq!(queryEncode("src/main.rs"), "src%2Fmain.rs");
+        assert_eq!(queryEncode("a b&c=d"), "a%20b%26c%3Dd");
+        assert_eq!(queryEncode("Az09-_.~"), "Az09-_.~");
+        assert_eq!(queryEncode("é"), "%C3%A9");
+    }
+
+    #[test]
+    fn escapesJsonStrings() {
+        assert_eq!(jsonString("plain"), "\"plain\"");
+        assert_eq!(jsonString("say \"hi\"\\"), "\"say \\\"hi\\\"\\\\\"");
+        assert_eq!(jsonString("a\nb\tc\r"), "\"a\\nb\\tc\\r\"");
+        assert_eq!(jsonString("\u{1}"), "\"\\u0001\"");
+        assert_eq!(jsonString("ünï"), "\"ünï\"");
+    }
+
+    #[test]
+    fn encodesBase64() {
+        assert_eq!(base64Encode(b""), "");
+        assert_eq!(base64Encode(b"f"), "Zg==");
+        assert_eq!(base64Encode(b"fo"), "Zm8=");
+        assert_eq!(base64Encode(b"foo"), "Zm9v");
+        assert_eq!(base64Encode(b"foobar"), "Zm9vYmFy");
+        assert_eq!(base64Encode(&[0xff, 0xfe, 0x00]), "//4A");
+    }
+}
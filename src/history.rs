@@ -0,0 +1,199 @@
+use fuser::FileType;
+use libc::{c_int, ENOENT, ENOTDIR};
+use std::fs;
+
+use github::{self, TreeEntry};
+use passthrough;
+use tree::{TreeRoot, Trees};
+use views::{self, ViewNode, ViewPath, Views};
+
+// `.history/<path>/`: for every file of the default branch, one `<date>-<short sha>`
+// file per commit that touched it, with the file as it was after that commit, and
+// `blame` with the author of every line, generated when it is opened. Directories of
+// the default branch are directories here too, so the history of a file is next to
+// the history of its siblings.
+
+const SHORT_SHA_LENGTH: usize = 7;
+const BLAME_FILE: &str = "blame";
+const BLAME_QUERY: &str = "query($owner: String!, $name: String!, $ref: String!, $path: String!) { repository(owner: $owner, name: $name) { object(expression: $ref) { ... on Commit { blame(path: $path) { ranges { startingLine endingLine commit { oid committedDate author { name } } } } } } } }";
+const BLAME_TEMPLATE: &str = ".data.repository.object.blame.ranges[] | [.startingLine, .endingLine, .commit.oid, .commit.committedDate[:10], .commit.author.name] | @tsv";
+
+struct Revision {
+    name: String,
+    sha: String,
+}
+
+fn defaultRoot(views: &mut Views, fullRepositoryName: &str) -> Result<TreeRoot, c_int> {
+    let defaultBranch = views.gh(&["api", &format!("repos/{}", fullRepositoryName), "--jq", ".default_branch"])?;
+    Ok(TreeRoot {
+        repository: fullRepositoryName.to_string(),
+        reference: defaultBranch,
+    })
+}
+
+// Newest first, like `git log`.
+fn listRevisions(views: &mut Views, fullRepositoryName: &str, relativePath: &str) -> Result<Vec<Revision>, c_int> {
+    let output = views.gh(&[
+        "api", "--paginate",
+        &format!("repos/{}/commits?path={}&per_page=100", fullRepositoryName, github::queryEncode(relativePath)),
+        "--jq", ".[] | [.sha, .commit.committer.date[:10]] | @tsv",
+    ])?;
+    let mut revisions = Vec::new();
+    for line in output.lines() {
+        let fields: Vec<&str> = line.split("\t").collect();
+        if let [sha, date] = fields.as_slice() {
+            revisions.push(Revision {
+                name: format!("{}-{}", date, &sha[..SHORT_SHA_LENGTH.min(sha.len())]),
+                sha: sha.to_string(),
+            });
+        }
+    }
+    Ok(revisions)
+}
+
+// The revision `name` (`<date>-<short sha>`) of the file at `relativePath`, when its
+// commit touched the file on that day.
+fn getRevision(views: &mut Views, fullRepositoryName: &str, relativePath: &str, name: &str) -> Result<Option<Revision>, c_int> {
+    let (date, shortSha) = match name.len() > 11 && name.as_bytes()[10] == b'-' {
+        true => (&name[..10], &name[11..]),
+        false => return Ok(None),
+    };
+    if shortSha.len() != SHORT_SHA_LENGTH || !shortSha.chars().all(|character| character.is_digit(16)) {
+        return Ok(None);
+    }
+    let touched = format!(
+        "[.files[]? | .filename, .previous_filename] | index({}) != null", github::jsonString(relativePath)
+    );
+    let output = match views.gh(&[
+        "api", &format!("repos/{}/commits/{}", fullRepositoryName, shortSha),
+        "--jq", &format!("[.sha, .commit.committer.date[:10], ({})] | @tsv", touched),
+    ]) {
+        Ok(output) => output,
+        Err(ENOENT) => return Ok(None),
+        Err(error) => return Err(error),
+    };
+    let fields: Vec<&str> = output.split("\t").collect();
+    Ok(match fields.as_slice() {
+        [sha, commitDate, "true"] if *commitDate == date && sha.starts_with(shortSha) => Some(Revision {
+            name: name.to_string(),
+            sha: sha.to_string(),
+        }),
+        _ => None,
+    })
+}
+
+// `git blame` like lines: `<short sha> (<author> <date> <line>) <contents>`.
+fn renderBlame(views: &mut Views, root: &TreeRoot, relativePath: &str, entry: &TreeEntry) -> Result<Vec<u8>, c_int> {
+    let (owner, name) = match root.repository.find("/") {
+        Some(index) => (&root.repository[..index], &root.repository[index + 1..]),
+        None => return Err(ENOENT),
+    };
+    let output = views.gh(&[
+        "api", "graphql", "-f", &format!("query={}", BLAME_QUERY), "-f", &format!("owner={}", owner),
+        "-f", &format!("name={}", name), "-f", &format!("ref={}", root.reference), "-f", &format!("path={}", relativePath),
+        "--jq", BLAME_TEMPLATE,
+    ])?;
+    let blobPath = github::fetchBlob(&root.repository, &entry.sha, &views.blobsDirectory).map_err(|error| views::errorCode(&error))?;
+    let contents = fs::read(&blobPath).map_err(|error| passthrough::errorCode(&error))?;
+    let lines: Vec<String> = String::from_utf8_lossy(&contents).lines().map(|line| line.to_string()).collect();
+    let mut blame = String::new();
+    for range in output.lines() {
+        let fields: Vec<&str> = range.split("\t").collect();
+        let (start, end, sha, date, author) = match fields.as_slice() {
+            [start, end, sha, date, author] => (start.parse::<usize>().unwrap_or(0), end.parse::<usize>().unwrap_or(0), *sha, *date, *author),
+            _ => continue,
+        };
+        for lineNumber in start.max(1)..end.min(lines.len()) + 1 {
+            blame.push_str(&format!(
+                "{} ({} {} {}) {}\n",
+                &sha[..SHORT_SHA_LENGTH.min(sha.len())], author, date, lineNumber, lines[lineNumber - 1]
+            ));
+        }
+    }
+    Ok(blame.into_bytes())
+}
+
+// The entry of the default branch at `relativePath`, None for its root.
+fn entryAt(trees: &mut Trees, root: &TreeRoot, relativePath: &str) -> Result<Option<TreeEntry>, c_int> {
+    match relativePath.len() {
+        0 => Ok(None),
+        _ => trees.remoteEntry(root, relativePath)?.map(Some).ok_or(ENOENT),
+    }
+}
+
+pub fn resolve(views: &mut Views, trees: &mut Trees, path: &ViewPath) -> Result<Option<ViewNode>, c_int> {
+    let root = defaultRoot(views, &path.repository)?;
+    let relativePath = path.rest.join("/");
+    match entryAt(trees, &root, &relativePath) {
+        Ok(Some(ref entry)) if entry.kind == "commit" => return Ok(None),
+        Ok(_) => return Ok(Some(ViewNode::Directory)),
+        Err(ENOENT) => (),
+        Err(error) => return Err(error),
+    }
+    // a revision or the blame of the file in the parent directory
+    let (name, filePath) = match path.rest.split_last() {
+        Some((name, parent)) if parent.len() > 0 => (name, parent.join("/")),
+        _ => return Ok(None),
+    };
+    match trees.remoteEntry(&root, &filePath)? {
+        Some(ref entry) if entry.kind == "blob" => (),
+        _ => return Ok(None),
+    }
+    if name == BLAME_FILE {
+        return Ok(Some(views.deferredNode(&path.fullPath)));
+    }
+    let revision = match getRevision(views, &path.repository, &filePath, name)? {
+        Some(revision) => revision,
+        None => return Ok(None),
+    };
+    let revisionRoot = TreeRoot {
+        repository: path.repository.clone(),
+        reference: revision.sha,
+    };
+    // the commit that deleted or renamed the file leaves nothing
+    Ok(Some(match trees.remoteEntry(&revisionRoot, &filePath)? {
+        Some(entry) => ViewNode::Blob {
+            repository: path.repository.clone(),
+            sha: entry.sha,
+            size: entry.size,
+        },
+        None => ViewNode::File(Vec::new()),
+    }))
+}
+
+// The contents of `<path>/blame`.
+pub fn render(views: &mut Views, trees: &mut Trees, path: &ViewPath) -> Result<Vec<u8>, c_int> {
+    let filePath = match path.rest.split_last() {
+        Some((name, parent)) if name == BLAME_FILE && parent.len() > 0 => parent.join("/"),
+        _ => return Err(ENOENT),
+    };
+    let root = defaultRoot(views, &path.repository)?;
+    match trees.remoteEntry(&root, &filePath)? {
+        Some(ref entry) if entry.kind == "blob" => renderBlame(views, &root, &filePath, entry),
+        _ => Err(ENOENT),
+    }
+}
+
+pub fn list(views: &mut Views, trees: &mut Trees, path: &ViewPath) -> Result<Vec<(String, FileType)>, c_int> {
+    let root = defaultRoot(views, &path.repository)?;
+    let relativePath = path.rest.join("/");
+    let treeSha = match entryAt(trees, &root, &relativePath)? {
+        None => trees.rootCommit(&root)?.1,
+        Some(ref entry) if entry.kind == "tree" => entry.sha.clone(),
+        Some(ref entry) if entry.kind == "blob" => {
+            let mut children = vec![(BLAME_FILE.to_string(), FileType::RegularFile)];
+            for revision in listRevisions(views, &path.repository, &relativePath)? {
+                children.push((revision.name, FileType::RegularFile));
+            }
+            return Ok(children);
+        },
+        Some(_) => return Err(ENOTDIR),
+    };
+    // every file has a directory with its history, submodules have none
+    Ok(trees
+        .listRemoteTree(&path.repository, &treeSha)?
+        .into_iter()
+        .filter(|entry| entry.kind != "commit")
+        .map(|entry| (entry.name, FileType::Directory))
+        .collect())
+}
@@ -0,0 +1,369 @@
+use fuser::FileType;
+use libc::{c_int, EEXIST, EINVAL, ENOENT, EPERM};
+use std::fs;
+use std::path::Path;
+
+use github;
+use passthrough;
+use views::{self, decodeName, encodeName, ViewNode, ViewPath, Views};
+
+// `.issues/<number>-<slug>.md`, one Markdown file per issue with its metadata as
+// front matter, the body and the comments. `open/`, `closed/` and `labels/<label>/`
+// show the same files filtered.
+//
+// `.issues/new/` is a drop directory: a file created there opens an issue when it is
+// closed. `.issues/<number>/comment` posts what is written to it as a comment.
+
+const ISSUE_TEMPLATE: &str = concat!(
+    r#""---\n"#,
+    r#"number: \(.number)\n"#,
+    r#"title: \(.title | tojson)\n"#,
+    r#"state: \(.state)\n"#,
+    r#"author: \(.user.login)\n"#,
+    r#"labels: \([.labels[].name] | tojson)\n"#,
+    r#"assignees: \([.assignees[].login] | tojson)\n"#,
+    r#"milestone: \(.milestone.title // "" | tojson)\n"#,
+    r#"created: \(.created_at)\n"#,
+    r#"updated: \(.updated_at)\n"#,
+    r#"url: \(.html_url)\n"#,
+    r#"---\n\n# \(.title)\n\n\(.body // "")\n""#,
+);
+pub const COMMENT_TEMPLATE: &str = r#".[] | "\n---\n\n**@\(.user.login)** commented on \(.created_at):\n\n\(.body // "")\n""#;
+
+pub struct IssueSummary {
+    pub number: u64,
+    pub title: String,
+    pub comments: u64,
+}
+
+impl IssueSummary {
+    pub fn fileName(&self) -> String {
+        let mut slug = String::new();
+        for character in self.title.to_lowercase().chars() {
+            if character.is_alphanumeric() {
+                slug.push(character);
+            } else if slug.len() > 0 && !slug.ends_with("-") {
+                slug.push('-');
+            }
+            if slug.chars().count() >= 50 {
+                break;
+            }
+        }
+        let slug = slug.trim_end_matches("-");
+        match slug.len() {
+            0 => format!("{}.md", self.number),
+            _ => format!("{}-{}.md", self.number, slug),
+        }
+    }
+}
+
+// `filter` is an extra query string, e.g. `state=open` or `labels=bug`. Pull requests
+// are issues too for the API, they are left out.
+pub fn listIssues(views: &mut Views, fullRepositoryName: &str, filter: &str) -> Result<Vec<IssueSummary>, c_int> {
+    let output = views.gh(&[
+        "api", "--paginate", &format!("repos/{}/issues?per_page=100&{}", fullRepositoryName, filter),
+        "--jq", ".[] | select(.pull_request == null) | [.number, .title, .comments] | @tsv",
+    ])?;
+    let mut issues = Vec::new();
+    for line in output.lines() {
+        let fields: Vec<&str> = line.split("\t").collect();
+        if fields.len() != 3 {
+            continue;
+        }
+        issues.push(IssueSummary {
+            number: fields[0].parse::<u64>().unwrap_or(0),
+            title: fields[1].to_string(),
+            comments: fields[2].parse::<u64>().unwrap_or(0),
+        });
+    }
+    Ok(issues)
+}
+
+struct Issue {
+    summary: IssueSummary,
+    state: String,
+    labels: Vec<String>,
+}
+
+// One issue, without listing the others. None for pull requests.
+fn getIssue(views: &mut Views, fullRepositoryName: &str, number: u64) -> Result<Option<Issue>, c_int> {
+    let output = match views.gh(&[
+        "api", &format!("repos/{}/issues/{}", fullRepositoryName, number),
+        "--jq", "select(.pull_request == null) | [.number, .title, .comments, .state] + [.labels[].name] | @tsv",
+    ]) {
+        Ok(output) => output,
+        Err(ENOENT) => return Ok(None),
+        Err(error) => return Err(error),
+    };
+    let fields: Vec<&str> = output.split("\t").collect();
+    if fields.len() < 4 {
+        return Ok(None);
+    }
+    Ok(Some(Issue {
+        summary: IssueSummary {
+            number: fields[0].parse::<u64>().unwrap_or(0),
+            title: fields[1].to_string(),
+            comments: fields[2].parse::<u64>().unwrap_or(0),
+        },
+        state: fields[3].to_string(),
+        labels: fields[4..].iter().map(|label| label.to_string()).collect(),
+    }))
+}
+
+fn listLabels(views: &mut Views, fullRepositoryName: &str) -> Result<Vec<String>, c_int> {
+    let output = views.gh(&[
+        "api", "--paginate", &format!("repos/{}/labels?per_page=100", fullRepositoryName), "--jq", ".[].name",
+    ])?;
+    Ok(output.lines().filter(|name| name.len() > 0).map(|name| name.to_string()).collect())
+}
+
+fn renderIssue(views: &mut Views, fullRepositoryName: &str, issue: &IssueSummary) -> Result<Vec<u8>, c_int> {
+    let issuePath = format!("repos/{}/issues/{}", fullRepositoryName, issue.number);
+    let mut contents = views.gh(&["api", &issuePath, "--jq", ISSUE_TEMPLATE])?;
+    contents.push('\n');
+    if issue.comments > 0 {
+        let comments = views.gh(&[
+            "api", "--paginate", &format!("{}/comments?per_page=100", issuePath), "--jq", COMMENT_TEMPLATE,
+        ])?;
+        contents.push_str(&comments);
+        contents.push('\n');
+    }
+    Ok(contents.into_bytes())
+}
+
+// The issues shown in the directory `rest` (relative to `.issues`), None when it is
+// not an issue directory.
+fn issuesIn(views: &mut Views, fullRepositoryName: &str, rest: &[String]) -> Result<Option<Vec<IssueSummary>>, c_int> {
+    let filter = match rest {
+        [] => "state=all".to_string(),
+        [ref state] if state == "open" || state == "closed" => format!("state={}", state),
+        [ref labels, ref label] if labels == "labels" => {
+            format!("state=all&labels={}", github::queryEncode(&decodeName(label)))
+        },
+        _ => return Ok(None),
+    };
+    listIssues(views, fullRepositoryName, &filter).map(Some)
+}
+
+// The number of `.issues/<number>`, when there is such an issue.
+fn issueNumber(views: &mut Views, fullRepositoryName: &str, name: &str) -> Result<Option<u64>, c_int> {
+    let number = match name.parse::<u64>() {
+        Ok(number) => number,
+        Err(_) => return Ok(None),
+    };
+    Ok(getIssue(views, fullRepositoryName, number)?.map(|issue| issue.summary.number))
+}
+
+// The issue `<number>-<slug>.md` in the directory `rest`, fetched on its own and
+// checked against the name and the filter of the directory.
+fn issueFile(views: &mut Views, fullRepositoryName: &str, rest: &[String], fileName: &str) -> Result<Option<IssueSummary>, c_int> {
+    let digits: String = fileName.chars().take_while(|character| character.is_ascii_digit()).collect();
+    let issue = match digits.parse::<u64>() {
+        Ok(number) => match getIssue(views, fullRepositoryName, number)? {
+            Some(issue) => issue,
+            None => return Ok(None),
+        },
+        Err(_) => return Ok(None),
+    };
+    let isInDirectory = match rest {
+        [] => true,
+        [ref state] if state == "open" || state == "closed" => issue.state == *state,
+        [ref labels, ref label] if labels == "labels" => issue.labels.contains(&decodeName(label)),
+        _ => false,
+    };
+    Ok(match isInDirectory && issue.summary.fileName() == fileName {
+        true => Some(issue.summary),
+        false => None,
+    })
+}
+
+pub fn resolve(views: &mut Views, path: &ViewPath) -> Result<Option<ViewNode>, c_int> {
+    match path.rest.as_slice() {
+        [] => return Ok(Some(ViewNode::Directory)),
+        [ref name] if name == "open" || name == "closed" || name == "labels" || name == "new" => {
+            return Ok(Some(ViewNode::Directory));
+        },
+        [ref new, _] if new == "new" => {
+            return Ok(views.inputNode(&path.fullPath));
+        },
+        [ref number] if issueNumber(views, &path.repository, number)?.is_some() => {
+            return Ok(Some(ViewNode::Directory));
+        },
+        [ref number, ref comment] if comment == "comment" && issueNumber(views, &path.repository, number)?.is_some() => {
+            return Ok(Some(views.inputNode(&path.fullPath).unwrap_or(ViewNode::Input(None))));
+        },
+        [ref labels, ref label] if labels == "labels" => {
+            let isLabel = listLabels(views, &path.repository)?.contains(&decodeName(label));
+            return Ok(if isLabel { Some(ViewNode::Directory) } else { None });
+        },
+        _ => (),
+    }
+    let (directory, fileName) = path.rest.split_at(path.rest.len() - 1);
+    match issueFile(views, &path.repository, directory, &fileName[0])? {
+        Some(issue) => renderIssue(views, &path.repository, &issue).map(|contents| Some(ViewNode::File(contents))),
+        None => Ok(None),
+    }
+}
+
+pub fn list(views: &mut Views, path: &ViewPath) -> Result<Vec<(String, FileType)>, c_int> {
+    let mut children = Vec::new();
+    match path.rest.as_slice() {
+        [] => {
+            children.push(("closed".to_string(), FileType::Directory));
+            children.push(("labels".to_string(), FileType::Directory));
+            children.push(("open".to_string(), FileType::Directory));
+        },
+        [ref name] if name == "labels" => {
+            for label in listLabels(views, &path.repository)? {
+                children.push((encodeName(&label), FileType::Directory));
+            }
+            return Ok(children);
+        },
+        [ref name] if name == "new" => {
+            let prefix = path.fullPath.clone() + "/";
+            for pendingPath in views.pendingInputs.keys() {
+                if pendingPath.starts_with(&prefix) {
+                    children.push((pendingPath[prefix.len()..].to_string(), FileType::RegularFile));
+                }
+            }
+            children.sort_by(|first, second| first.0.cmp(&second.0));
+            return Ok(children);
+        },
+        [ref number] if issueNumber(views, &path.repository, number)?.is_some() => {
+            children.push(("comment".to_string(), FileType::RegularFile));
+            return Ok(children);
+        },
+        _ => (),
+    }
+    match issuesIn(views, &path.repository, &path.rest)? {
+        Some(issues) => {
+            for issue in issues {
+                children.push((issue.fileName(), FileType::RegularFile));
+            }
+            Ok(children)
+        },
+        None => Err(ENOENT),
+    }
+}
+
+pub fn create(views: &mut Views, path: &ViewPath) -> Result<(), c_int> {
+    match path.rest.as_slice() {
+        [ref new, _] if new == "new" => {
+            if views.pendingInputs.contains_key(&path.fullPath) {
+                return Err(EEXIST);
+            }
+            views.startInput(&path.fullPath).map(|_| ())
+        },
+        _ => Err(EPERM),
+    }
+}
+
+pub fn submit(views: &mut Views, path: &ViewPath, inputPath: &Path) -> Result<(), c_int> {
+    let contents = fs::read(inputPath).map_err(|error| passthrough::errorCode(&error))?;
+    let contents = String::from_utf8_lossy(&contents).to_string();
+    let result = match path.rest.as_slice() {
+        [ref new, _] if new == "new" => {
+            let (title, body, labels, assignees) = parseNewIssue(&contents).ok_or_else(|| {
+                eprintln!("gh-mount: {} has no title, no issue was opened", path.fullPath);
+                EINVAL
+            })?;
+            github::createIssue(&path.repository, &title, &body, &labels, &assignees).map(|_| ())
+        },
+        [ref number, _] => {
+            if contents.trim().len() == 0 {
+                return Ok(());
+            }
+            let number = number.parse::<u64>().map_err(|_| ENOENT)?;
+            github::createIssueComment(&path.repository, number, contents.trim())
+        },
+        _ => return Err(EPERM),
+    };
+    views.invalidate(&path.repository);
+    result.map_err(|error| views::errorCode(&error))
+}
+
+// An optional `---` front matter with `labels` and `assignees` (comma separated or
+// `[a, b]`), then the title on the first line and the body.
+fn parseNewIssue(contents: &str) -> Option<(String, String, Vec<String>, Vec<String>)> {
+    let mut labels = Vec::new();
+    let mut assignees = Vec::new();
+    let mut lines = contents.lines().peekable();
+    if lines.peek().map(|line| line.trim() == "---").unwrap_or(false) {
+        lines.next();
+        while let Some(line) = lines.next() {
+            if line.trim() == "---" {
+                break;
+            }
+            let (key, value) = match line.find(":") {
+                Some(index) => (line[..index].trim(), line[index + 1..].trim()),
+                None => continue,
+            };
+            let values: Vec<String> = value
+                .trim_start_matches("[")
+                .trim_end_matches("]")
+                .split(",")
+                .map(|value| value.trim().trim_matches('"').to_string())
+                .filter(|value| value.len() > 0)
+                .collect();
+            match key {
+                "labels" => labels = values,
+                "assignees" => assignees = values,
+                _ => (),
+            }
+        }
+    }
+    let mut lines = lines.skip_while(|line| line.trim().len() == 0);
+    let title = lines.next()?.trim().trim_start_matches("#").trim().to_string();
+    if title.len() == 0 {
+        return None;
+    }
+    let body = lines.collect::<Vec<&str>>().join("\n").trim().to_string();
+    Some((title, body, labels, assignees))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn summary(number: u64, title: &str) -> IssueSummary {
+        IssueSummary { number: number, title: title.to_string(), comments: 0 }
+    }
+
+    #[test]
+    fn namesIssueFilesAfterTheirTitle() {
+        assert_eq!(summary(12, "Crash on startup!").fileName(), "12-crash-on-startup.md");
+        assert_eq!(summary(3, "  [Docs]  fix   typos ").fileName(), "3-docs-fix-typos.md");
+        assert_eq!(summary(4, "Ünïcode títle").fileName(), "4-ünïcode-títle.md");
+        assert_eq!(summary(7, "???").fileName(), "7.md");
+        let longTitle = summary(9, &"word ".repeat(30)).fileName();
+        assert!(longTitle.len() <= "9-.md".len() + 50);
+        assert!(!longTitle.ends_with("-.md"));
+    }
+
+    #[test]
+    fn parsesTitleAndBody() {
+        let (title, body, labels, assignees) = parseNewIssue("\n# Crash on start\n\nSteps:\n1. run it\n").unwrap();
+        assert_eq!(title, "Crash on start");
+        assert_eq!(body, "Steps:\n1. run it");
+        assert!(labels.is_empty() && assignees.is_empty());
+        assert_eq!(parseNewIssue("Only a title").unwrap().1, "");
+    }
+
+    #[test]
+    fn parsesFrontMatter() {
+        let contents = "---\nlabels: [bug, \"good first issue\"]\nassignees: ana, bob\nmilestone: v2\n---\nTitle\nBody";
+        let (title, body, labels, assignees) = parseNewIssue(contents).unwrap();
+        assert_eq!(title, "Title");
+        assert_eq!(body, "Body");
+        assert_eq!(labels, vec!["bug", "good first issue"]);
+        assert_eq!(assignees, vec!["ana", "bob"]);
+    }
+
+    #[test]
+    fn rejectsIssuesWithoutTitle() {
+        assert!(parseNewIssue("").is_none());
+        assert!(parseNewIssue("\n\n  \n").is_none());
+        assert!(parseNewIssue("#\nBody").is_none());
+        assert!(parseNewIssue("---\nlabels: bug\n---\n").is_none());
+    }
+}
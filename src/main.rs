@@ -1,24 +1,69 @@
 extern crate fuser;
 extern crate libc;
 extern crate time;
+
+mod actions;
+mod attributes;
+mod clone;
+mod clone_store;
+mod commits;
+mod compare;
+mod config;
+mod fetcher;
+mod gists;
+mod github;
+mod history;
+mod issues;
+mod metadata;
+mod overlay;
+mod passthrough;
+mod publish;
+mod pulls;
+mod refs;
+mod releases;
+mod snapshots;
+mod storage;
+mod tree;
+mod views;
+
+use attributes::RepositoryDetails;
+use clone_store::CloneStore;
+use config::MountConfig;
+use fetcher::Fetcher;
+use publish::PublishError;
+use storage::Storage;
+use tree::{TreeLocation, Trees};
+use views::{ViewPath, Views};
 use fuser::{
-    FileAttr, FileType, Filesystem, ReplyAttr, ReplyData, ReplyDirectory, ReplyEmpty, ReplyEntry,
-    ReplyOpen, ReplyStatfs, ReplyWrite, Request,
+    FileAttr, FileType, Filesystem, ReplyAttr, ReplyCreate, ReplyData, ReplyDirectory, ReplyEmpty,
+    ReplyEntry, ReplyOpen, ReplyStatfs, ReplyWrite, ReplyXattr, Request,
 };
 use libc::c_int;
-use libc::{EEXIST, ENOENT, ENOSYS, EPERM};
+use libc::{EACCES, EBUSY, EEXIST, EIO, EINVAL, ENOENT, ENOSYS, EPERM, EROFS};
 use std::collections::HashMap;
 use std::env;
 use std::ffi::OsStr;
-use std::path::Path;
-use std::process::Command;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::{self, Command};
 use std::time::{Duration,SystemTime};
 use std::option::Option;
 
+// Commands written to this file at the mount root: `clone owner/repo` materializes a
+// clone, `publish owner/repo[@ref] [branch [message...]]` commits the local edits.
+const CONTROL_FILE_NAME: &str = ".control";
+const CONTROL_FILE_INODE: u64 = 2;
+
+#[cfg(target_os = "macos")]
+const NO_ATTRIBUTE: c_int = libc::ENOATTR;
+#[cfg(not(target_os = "macos"))]
+const NO_ATTRIBUTE: c_int = libc::ENODATA;
+
 enum GithubVirtualFileSystemPath {
     RepositoryPath,
     UserPath,
     FilePath,
+    ControlPath,
     None
 }
 impl GithubVirtualFileSystemPath {
@@ -27,6 +72,7 @@ impl GithubVirtualFileSystemPath {
             GithubVirtualFileSystemPath::RepositoryPath => "repo",
             GithubVirtualFileSystemPath::UserPath => "user",
             GithubVirtualFileSystemPath::FilePath => "file",
+            GithubVirtualFileSystemPath::ControlPath => "control",
             GithubVirtualFileSystemPath::None => "none",
         }
     }
@@ -38,38 +84,51 @@ struct InodesTypes {
 }
 struct GithubVirtualFileSystem {
     repositoriesPerUser: HashMap<String, Vec<String>>,
+    repositoryDetails: HashMap<String, RepositoryDetails>,
     inodes: HashMap<String, u64>,
+    paths: HashMap<u64, String>,
+    lastInode: u64,
     attrs: HashMap<u64, FileAttr>,
+    config: MountConfig,
+    storage: Storage,
+    cloneStore: CloneStore,
+    fetcher: Fetcher,
+    trees: Trees,
+    views: Views,
 }
 
 impl GithubVirtualFileSystem {
-    fn new() -> GithubVirtualFileSystem {
+    fn new(config: MountConfig, storage: Storage) -> GithubVirtualFileSystem {
         let mut inodes = HashMap::new();
         let mut attrs = HashMap::new();
         let ts = SystemTime::now();
-        let attr = FileAttr {
-            ino: 1,
-            size: 0,
-            blocks: 0,
-            atime: ts,
-            mtime: ts,
-            ctime: ts,
-            crtime: ts,
-            kind: FileType::Directory,
-            perm: 0o755,
-            nlink: 0,
-            uid: 0,
-            gid: 0,
-            rdev: 0,
-            flags: 0,
-            blksize: 0,
-        };
+        let owner = config.owner();
+        let attr = attributes::nodeAttr(1, FileType::Directory, 0o755, 0, ts, owner);
         attrs.insert(1, attr);
         inodes.insert("/".to_string(), 1);
+        let controlAttr = FileAttr {
+            ino: CONTROL_FILE_INODE,
+            kind: FileType::RegularFile,
+            perm: 0o644,
+            ..attr
+        };
+        attrs.insert(CONTROL_FILE_INODE, controlAttr);
+        inodes.insert(CONTROL_FILE_NAME.to_string(), CONTROL_FILE_INODE);
+        let paths = inodes.iter().map(|(path, inode)| (*inode, path.clone())).collect();
+        let cloneStore = CloneStore::load(&config, &storage);
         GithubVirtualFileSystem {
             repositoriesPerUser: HashMap::new(),
+            repositoryDetails: HashMap::new(),
             inodes: inodes,
+            paths: paths,
+            lastInode: CONTROL_FILE_INODE,
             attrs: attrs,
+            fetcher: Fetcher::new(&config, &storage.clonesDirectory, cloneStore.fetching()),
+            cloneStore: cloneStore,
+            trees: Trees::new(&storage.blobsDirectory, &storage.overlaysDirectory, config.lastCommitTimes, owner),
+            views: Views::new(&storage, owner),
+            config: config,
+            storage: storage,
         }
     }
     fn getTypeFromPath(fullRepositoryName: &str) -> GithubVirtualFileSystemPath {
@@ -78,40 +137,21 @@ impl GithubVirtualFileSystem {
         if isRoot {
             return GithubVirtualFileSystemPath::None
         }
+        let isControl = fullRepositoryName == CONTROL_FILE_NAME;
+        if isControl {
+            return GithubVirtualFileSystemPath::ControlPath
+        }
         let isUser = fullpathSplitted.len() == 1;
         if isUser {
             return GithubVirtualFileSystemPath::UserPath
         }
-        let isRepo = fullpathSplitted.len() == 2;
+        // `owner/.gists` is a special directory, not a repository
+        let isRepo = fullpathSplitted.len() == 2 && !views::isViewPath(fullRepositoryName);
         if isRepo {
             return GithubVirtualFileSystemPath::RepositoryPath
         }
         return GithubVirtualFileSystemPath::FilePath
     }
-    fn getFilesFromRepo(&self, fullRepositoryName: &str) -> HashMap<String, u64> {
-        let inodes = self.getInodesPerType();
-        let mut filesFromRepository = HashMap::new();
-        let repository = GithubVirtualFileSystem::parseRepositoryName(fullRepositoryName)[1];
-        for (pathname, pathInode) in inodes.repositoriesInodes.iter() {
-            let fullpathSplitted = GithubVirtualFileSystem::parseRepositoryName(&pathname);
-            let fileName = match self.getCurrentPathType(*pathInode).0 {
-                GithubVirtualFileSystemPath::UserPath =>  Option::None,
-                GithubVirtualFileSystemPath::RepositoryPath => Option::None,
-                GithubVirtualFileSystemPath::FilePath => Option::Some(fullpathSplitted),
-                GithubVirtualFileSystemPath::None => Option::None,
-            };
-            match fileName {
-                Some(fileName) => {
-                    let currentRepository = fileName[1];
-                    if currentRepository == repository  {
-                        filesFromRepository.insert(pathname.as_str().to_string(), (*pathInode) as u64);
-                    }
-                },
-                None => ()
-            }
-        }
-        return filesFromRepository;
-    }
     fn getRepositoriesFromUser(&self, usernameRaw: &str) -> HashMap<String, u64> {
         let hasToParseUserName = usernameRaw.contains("/");
         let username = match hasToParseUserName {
@@ -126,6 +166,7 @@ impl GithubVirtualFileSystem {
                 GithubVirtualFileSystemPath::UserPath =>  Option::None,
                 GithubVirtualFileSystemPath::RepositoryPath => Option::Some(fullpathSplitted),
                 GithubVirtualFileSystemPath::FilePath =>  Option::None,
+                GithubVirtualFileSystemPath::ControlPath => Option::None,
                 GithubVirtualFileSystemPath::None => Option::None,
             };
             match repositoryName {
@@ -140,7 +181,7 @@ impl GithubVirtualFileSystem {
         return repositoriesFromUser;
     }
     fn getCurrentPathType(&self, inode: u64) -> (GithubVirtualFileSystemPath, &str) {
-        let fullRepositoryName = GithubVirtualFileSystem::findRepositoryNamePerInode(&self.inodes, &inode);
+        let fullRepositoryName = GithubVirtualFileSystem::findRepositoryNamePerInode(&self.paths, &inode);
         let pathtype = GithubVirtualFileSystem::getTypeFromPath(fullRepositoryName);
         return (pathtype, fullRepositoryName);
     }
@@ -154,6 +195,7 @@ impl GithubVirtualFileSystem {
                 GithubVirtualFileSystemPath::UserPath => usersInodes.insert(pathname.to_string(), pathInode.to_owned()),
                 GithubVirtualFileSystemPath::RepositoryPath =>  repositoriesInodes.insert(pathname.to_string(), pathInode.to_owned()),
                 GithubVirtualFileSystemPath::FilePath => filesInodes.insert(pathname.to_string(), pathInode.to_owned()),
+                GithubVirtualFileSystemPath::ControlPath => Option::None,
                 GithubVirtualFileSystemPath::None => Option::None,
             };
         }
@@ -172,17 +214,49 @@ impl GithubVirtualFileSystem {
     fn parseRepositoryName(fullRepositoryName: &str) -> Vec<&str> {
         return fullRepositoryName.split("/").collect();
     }
-    fn findRepositoryNamePerInode<'a>(inodes: &'a HashMap<String, u64>, inode: &u64) -> &'a str {
-        for (repoName, repoInode) in inodes.iter() {
-            if repoInode == inode {
-                return repoName;
+    fn findRepositoryNamePerInode<'a>(paths: &'a HashMap<u64, String>, inode: &u64) -> &'a str {
+        match paths.get(inode) {
+            Some(repoName) => repoName,
+            None => "",
+        }
+    }
+    fn insertInode(&mut self, key: String, inode: u64) -> () {
+        self.lastInode = self.lastInode.max(inode);
+        self.paths.insert(inode, key.clone());
+        self.inodes.insert(key, inode);
+    }
+    fn getOrCreateInode(&mut self, key: String) -> u64 {
+        match self.inodes.get(&key) {
+            Some(inode) => *inode,
+            None => {
+                let newInode = self.lastInode + 1;
+                self.insertInode(key, newInode);
+                newInode
             }
         }
-        return "";
+    }
+    // Moves the inodes of `from` and everything below it over to `to`.
+    fn renameInodes(&mut self, from: &str, to: &str) -> () {
+        let prefix = from.to_string() + "/";
+        let renamedKeys: Vec<String> = self.inodes
+            .keys()
+            .filter(|key| *key == from || key.starts_with(&prefix))
+            .cloned()
+            .collect();
+        if let Some(inode) = self.inodes.get(to).cloned() {
+            self.paths.remove(&inode);
+        }
+        for key in renamedKeys {
+            let inode = self.inodes.remove(&key).unwrap();
+            let newKey = to.to_string() + &key[from.len()..];
+            self.paths.insert(inode, newKey.clone());
+            self.inodes.insert(newKey, inode);
+        }
     }
     fn addUser(&mut self, username: &str) -> () {
         let args = [
-            "repo", "list", username, "--json", "name", "--source", "--jq", ".[].name",
+            "repo", "list", username, "--json", "name,stargazerCount,visibility,pushedAt,createdAt", "--source",
+            "--jq", ".[] | [.name, .stargazerCount, (.visibility | ascii_downcase), .pushedAt, .createdAt] | @tsv",
         ];
         let allUsers = self.getInodesPerType().usersInodes;
         let ignoreUsernames = [
@@ -205,144 +279,534 @@ impl GithubVirtualFileSystem {
             .output()
             .expect(format!("Error when running: gh {:?}", args.join(" ").as_str()).as_str());
         let stdout = String::from_utf8(listOutput.stdout).unwrap();
-        let result: Vec<String> = stdout.split("\n").map(|s| s.to_string()).collect();
+        let mut result: Vec<String> = Vec::new();
+        for line in stdout.split("\n") {
+            let fields: Vec<&str> = line.split("\t").collect();
+            if let [name, stars, visibility, pushedAt, createdAt] = fields.as_slice() {
+                let fullRepositoryName = self.formatRepositoryName(username, name);
+                self.repositoryDetails.insert(fullRepositoryName, RepositoryDetails {
+                    stars: stars.parse::<u64>().unwrap_or(0),
+                    visibility: visibility.to_string(),
+                    pushedAt: github::parseTime(pushedAt),
+                    createdAt: github::parseTime(createdAt),
+                });
+            }
+            result.push(fields[0].to_string());
+        }
         let mut repositoriesPerUser = HashMap::new();
         repositoriesPerUser.insert(username.to_string(), result);
         self.repositoriesPerUser = repositoriesPerUser;
-        let repos = self.repositoriesPerUser.get(username).unwrap();
+        let repos = self.repositoriesPerUser.get(username).unwrap().clone();
 
-        let mut index = self.inodes.len() as u64;
+        let mut index = self.lastInode;
         let userInode: u64 = index + 1;
-        let ts = SystemTime::now();
+        // organizations and users alike, the directory is as old as the account
+        let ts = match github::accountCreationTime(username) {
+            Ok(Some(createdAt)) => createdAt,
+            Ok(None) => SystemTime::now(),
+            Err(error) => {
+                eprintln!("gh-mount: {}", error);
+                SystemTime::now()
+            }
+        };
+        let owner = self.config.owner();
+        // `.` and the parent, plus the `..` of every repository
         let userAttr = FileAttr {
-            ino: userInode,
-            size: username.to_string().len() as u64,
-            blocks: 0,
-            atime: ts,
-            mtime: ts,
-            ctime: ts,
-            crtime: ts,
-            kind: FileType::Directory,
-            perm: 0o644,
-            nlink: 0,
-            uid: 0,
-            gid: 0,
-            rdev: 0,
-            flags: 0,
-            blksize: 0,
+            nlink: 2 + repos.iter().filter(|repoName| repoName.len() > 0).count() as u32,
+            ..attributes::nodeAttr(userInode, FileType::Directory, 0o755, 0, ts, owner)
         };
-        self.inodes.insert(username.to_string(), userAttr.ino);
+        self.insertInode(username.to_string(), userAttr.ino);
         self.attrs.insert(userInode, userAttr);
         for repoName in repos.iter() {
             if repoName.len() == 0 {
                 continue;
             };
-            let newInode: u64 = self.inodes.len() as u64 + 1;
+            let newInode: u64 = self.lastInode + 1;
             let key = self.formatRepositoryName(username, repoName);
-            let ts = SystemTime::now();
+            let now = SystemTime::now();
+            let (pushedAt, createdAt) = match self.repositoryDetails.get(&key) {
+                Some(details) => (details.pushedAt.unwrap_or(now), details.createdAt.unwrap_or(now)),
+                None => (now, now),
+            };
             let attr = FileAttr {
-                ino: newInode,
-                size: repoName.len() as u64,
-                blocks: 0,
-                atime: ts,
-                mtime: ts,
-                ctime: ts,
-                crtime: ts,
-                kind: FileType::Directory,
-                perm: 0o644,
-                nlink: 0,
-                uid: 0,
-                gid: 0,
-                rdev: 0,
-                flags: 0,
-                blksize: 0,
+                crtime: createdAt,
+                ..attributes::nodeAttr(newInode, FileType::Directory, 0o755, 0, pushedAt, owner)
             };
-            if !self.inodes.contains_key(&key) { self.inodes.insert(key, attr.ino); } ;
+            if !self.inodes.contains_key(&key) { self.insertInode(key, attr.ino); } ;
             if !self.attrs.contains_key(&newInode) { self.attrs.insert(newInode, attr); } ;
         }
     }
-    fn addRepoFiles(&mut self, fullRepositoryName: &str) -> () {
-        let userAndRepo: Vec<&str> = GithubVirtualFileSystem::parseRepositoryName(fullRepositoryName);
-        let username = userAndRepo[0];
-        let repoName =  userAndRepo[1];
-        let args = [
-            "api", &format!("repos/{}/{}/git/trees/HEAD", username, repoName), "--jq", ".tree[].path"
-        ];
-       // println!("args={:?}", args);
-        let listOutput = Command::new("gh")
-            .args(args)
-            .output()
-            .expect(format!("Error when running: gh {:?}", args.join(" ").as_str()).as_str());
-        let stdout = String::from_utf8(listOutput.stdout).unwrap();
-        let result: Vec<String> = stdout.split("\n").map(|s| s.to_string()).collect();
-        for filename in result {
-            if repoName.len() == 0 {
-                continue;
-            };
-            let newInode: u64 = self.inodes.len() as u64 + 1;
-            let key = self.formatRepositoryName(username, repoName) + "/" +&filename;
-            let ts = SystemTime::now();
-            let attr = FileAttr {
-                ino: newInode,
-                size: repoName.len() as u64,
-                blocks: 0,
-                atime: ts,
-                mtime: ts,
-                ctime: ts,
-                crtime: ts,
-                kind: FileType::Directory,
-                perm: 0o644,
-                nlink: 0,
-                uid: 0,
-                gid: 0,
-                rdev: 0,
-                flags: 0,
-                blksize: 0,
-            };
-            if !self.inodes.contains_key(&key) { self.inodes.insert(key, attr.ino); } ;
-            if !self.attrs.contains_key(&newInode) { self.attrs.insert(newInode, attr); } ;
-            
+    fn runControlCommand(&mut self, command: &str) -> Result<(), c_int> {
+        let commandSplitted: Vec<&str> = command.split_whitespace().collect();
+        match commandSplitted.as_slice() {
+            [] => Ok(()),
+            ["clone", fullRepositoryName] => {
+                if !clone::isValidRepositoryName(fullRepositoryName) {
+                    return Err(EINVAL);
+                }
+                self.cloneStore.materialize(&self.config, &self.storage, fullRepositoryName).map_err(|error| {
+                    eprintln!("gh-mount: clone of {} failed: {}", fullRepositoryName, error);
+                    EIO
+                })
+            },
+            ["publish", ref arguments @ ..] => match publish::publishCommand(&mut self.trees, arguments) {
+                Ok(commitSha) => {
+                    eprintln!("gh-mount: published {} as {}", arguments[0], commitSha);
+                    Ok(())
+                },
+                Err(error) => {
+                    eprintln!("gh-mount: publish failed: {}", error.message());
+                    match error {
+                        PublishError::Conflict(_) => Err(EBUSY),
+                        PublishError::Failed(_) => Err(EIO),
+                    }
+                },
+            },
+            _ => Err(EINVAL),
+        }
+    }
+    // Where `user/repo/some/path` lives on disk when `user/repo` has been cloned.
+    fn getClonePath(&self, fullPath: &str) -> Option<PathBuf> {
+        let fullpathSplitted = GithubVirtualFileSystem::parseRepositoryName(fullPath);
+        if fullpathSplitted.len() < 2 || views::isViewPath(fullPath) {
+            return None;
+        }
+        let fullRepositoryName = self.formatRepositoryName(fullpathSplitted[0], fullpathSplitted[1]);
+        if !self.cloneStore.isCloned(&fullRepositoryName) {
+            return None;
+        }
+        let mut clonePath = self.storage.clonePath(&fullRepositoryName);
+        for pathPart in fullpathSplitted[2..].iter() {
+            clonePath.push(pathPart);
+        }
+        Some(clonePath)
+    }
+    // The repository whose clone serves `fullPath`, if any.
+    fn getCloneRepository(&self, fullPath: &str) -> Option<String> {
+        self.getClonePath(fullPath)?;
+        let pathParts = GithubVirtualFileSystem::parseRepositoryName(fullPath);
+        Some(self.formatRepositoryName(pathParts[0], pathParts[1]))
+    }
+    // Inode of a path inside a clone, with its attributes refreshed from disk.
+    fn lookupInClone(&mut self, fullPath: String) -> Option<FileAttr> {
+        let clonePath = self.getClonePath(&fullPath)?;
+        let metadata = fs::symlink_metadata(&clonePath).ok()?;
+        let inode = self.getOrCreateInode(fullPath);
+        let attr = passthrough::attrFromMetadata(inode, &metadata, self.config.owner());
+        self.attrs.insert(inode, attr);
+        Some(attr)
+    }
+    // Inode of a path inside a repository, served from its clone when there is one and
+    // from the remote tree plus local edits otherwise.
+    fn lookupPath(&mut self, fullPath: String) -> Option<FileAttr> {
+        if self.getClonePath(&fullPath).is_some() {
+            return self.lookupInClone(fullPath);
+        }
+        let location = match self.locateTree(&fullPath) {
+            Some(location) => location,
+            None => {
+                let viewPath = views::parseViewPath(&fullPath)?;
+                let node = self.views.resolve(&mut self.trees, &viewPath).ok()??;
+                let inode = self.getOrCreateInode(fullPath);
+                let attr = self.views.attr(inode, &node);
+                self.attrs.insert(inode, attr);
+                return Some(attr);
+            }
+        };
+        let node = self.trees.resolve(&location).ok()??;
+        let inode = self.getOrCreateInode(fullPath);
+        let attr = self.trees.attr(inode, &location, &node)?;
+        self.attrs.insert(inode, attr);
+        Some(attr)
+    }
+    // `mkdir owner/name`: creates the repository on GitHub and adds it to the listing.
+    fn createRepository(&mut self, owner: &str, name: &str) -> Result<FileAttr, c_int> {
+        let fullRepositoryName = self.formatRepositoryName(owner, name);
+        // `owner/.gists` is taken by the account view
+        if self.inodes.contains_key(&fullRepositoryName) || views::ACCOUNT_VIEWS.contains(&name) {
+            return Err(EEXIST);
+        }
+        // GitHub would rename the repository (`my repo` becomes `my-repo`, `repo.git`
+        // becomes `repo`), leaving the new directory pointing at nothing
+        if !clone::isValidRepositoryName(&fullRepositoryName) || name.ends_with(".git") {
+            return Err(EINVAL);
+        }
+        if let Err(error) = github::createRepository(owner, name, &self.config.repositoryVisibility) {
+            eprintln!("gh-mount: could not create {}: {}", fullRepositoryName, error);
+            return Err(if github::isForbidden(&error) || github::isNotFound(&error) {
+                EACCES
+            } else if error.contains("HTTP 422") && error.contains("already exists") {
+                EEXIST
+            } else if error.contains("HTTP 422") {
+                // validation failures, like `internal` visibility on a personal account
+                EINVAL
+            } else {
+                EIO
+            });
+        }
+        self.repositoryDetails.insert(fullRepositoryName.clone(), RepositoryDetails {
+            stars: 0,
+            visibility: self.config.repositoryVisibility.clone(),
+            pushedAt: Some(SystemTime::now()),
+            createdAt: Some(SystemTime::now()),
+        });
+        let inode = self.getOrCreateInode(fullRepositoryName);
+        let attr = attributes::nodeAttr(inode, FileType::Directory, 0o755, 0, SystemTime::now(), self.config.owner());
+        self.attrs.insert(inode, attr);
+        // the `..` of the new repository
+        if let Some(ownerInode) = self.inodes.get(owner).cloned() {
+            if let Some(ownerAttr) = self.attrs.get_mut(&ownerInode) {
+                ownerAttr.nlink += 1;
+            }
+        }
+        Ok(attr)
+    }
+    // The `user.github.*` attributes of `fullPath`, see attributes.rs.
+    // Only what `wanted` needs is computed, everything for None (`listxattr`).
+    fn getExtendedAttributes(&mut self, fullPath: &str, wanted: Option<&str>) -> Vec<(&'static str, String)> {
+        let pathParts = GithubVirtualFileSystem::parseRepositoryName(fullPath);
+        if pathParts.len() < 2 {
+            return Vec::new();
+        }
+        let fullRepositoryName = self.formatRepositoryName(pathParts[0], pathParts[1]);
+        let mut extendedAttributes = Vec::new();
+        if let GithubVirtualFileSystemPath::RepositoryPath = GithubVirtualFileSystem::getTypeFromPath(fullPath) {
+            let details = self.repositoryDetails.get(&fullRepositoryName);
+            extendedAttributes.extend(attributes::repositoryAttributes(&fullRepositoryName, details));
         }
+        let isWanted = |name: &str| wanted.map(|wanted| wanted == name).unwrap_or(true);
+        if wanted.is_some() && extendedAttributes.iter().any(|&(name, _)| isWanted(name)) {
+            extendedAttributes.retain(|&(name, _)| isWanted(name));
+            return extendedAttributes;
+        }
+        let nodeAttributes = if self.getClonePath(fullPath).is_some() {
+            let clonePath = self.storage.clonePath(&fullRepositoryName);
+            attributes::cloneAttributes(&fullRepositoryName, &clonePath, &pathParts[2..].join("/"), wanted)
+        } else if let Some(location) = self.locateTree(fullPath) {
+            // the ref is known without looking the node up
+            match wanted {
+                Some(attributes::REF_ATTRIBUTE) => vec![(attributes::REF_ATTRIBUTE, location.root.reference.clone())],
+                _ => match self.trees.resolve(&location) {
+                    Ok(Some(node)) => attributes::treeAttributes(&location, &node),
+                    _ => Vec::new(),
+                },
+            }
+        } else {
+            match self.views.refSources.get(fullPath) {
+                Some(source) => vec![(refs::SOURCE_ATTRIBUTE, source.clone())],
+                None => Vec::new(),
+            }
+        };
+        // the repository keeps its own URL rather than the one of its tree
+        for (name, value) in nodeAttributes {
+            if !extendedAttributes.iter().any(|&(existingName, _)| existingName == name) {
+                extendedAttributes.push((name, value));
+            }
+        }
+        extendedAttributes.retain(|&(name, _)| isWanted(name));
+        extendedAttributes
+    }
+    // The tree `fullPath` belongs to. Inside the special directories of a repository
+    // only the trees they expose count, `user/repo/.refs` is not a path of the default
+    // branch.
+    fn locateTree(&mut self, fullPath: &str) -> Option<TreeLocation> {
+        // account views have no repository to take a tree from, and `repo@{date}` only
+        // has the tree registered for it
+        if let Some(viewPath) = views::parseViewPath(fullPath) {
+            let pathParts = GithubVirtualFileSystem::parseRepositoryName(fullPath);
+            let isInRepository = viewPath.repository.len() > 0 && fullPath.starts_with(&(viewPath.repository.clone() + "/"));
+            if !isInRepository && !self.trees.isRegistered(&pathParts[..2].join("/")) {
+                return None;
+            }
+        }
+        let location = self.trees.locate(fullPath)?;
+        if views::isViewPath(fullPath) && !views::isViewPath(&location.rootPath) {
+            return None;
+        }
+        Some(location)
+    }
+    // The entry of a special directory `fullPath` is, None for the files of the trees
+    // they expose (but not for the directories showing them, so they can be removed).
+    fn getViewPath(&mut self, fullPath: &str) -> Option<ViewPath> {
+        let viewPath = views::parseViewPath(fullPath)?;
+        match self.locateTree(fullPath) {
+            Some(ref location) if location.relativePath.len() > 0 => None,
+            _ => Some(viewPath),
+        }
+    }
+    fn getChildPath(&self, parent: u64, name: &OsStr) -> Option<String> {
+        match self.getCurrentPathType(parent) {
+            (GithubVirtualFileSystemPath::RepositoryPath, fullPath) | (GithubVirtualFileSystemPath::FilePath, fullPath) => {
+                Some(fullPath.to_string() + "/" + &name.to_string_lossy())
+            },
+            _ => None,
+        }
+    }
+    // Where a modification of `fullPath` goes: the clone, or the overlay of the tree.
+    fn getWriteTarget(&mut self, fullPath: &str) -> Result<Result<PathBuf, TreeLocation>, c_int> {
+        if let Some(clonePath) = self.getClonePath(fullPath) {
+            return Ok(Ok(clonePath));
+        }
+        match self.locateTree(fullPath) {
+            Some(ref location) if location.root.isCommit() || refs::isTagRoot(&location.rootPath) => Err(EROFS),
+            Some(location) => Ok(Err(location)),
+            None if views::isViewPath(fullPath) => Err(EROFS),
+            None => Err(EIO),
+        }
+    }
+    fn addChildren(&mut self, reply: &mut ReplyDirectory, fullPath: &str, children: Vec<(String, FileType)>, offset: i64) -> () {
+        let skippedChildren = (offset as usize).saturating_sub(2);
+        for (index, (childName, kind)) in children.into_iter().enumerate().skip(skippedChildren) {
+            let inode = self.getOrCreateInode(fullPath.to_string() + "/" + &childName);
+            if reply.add(inode, index as i64 + 3, kind, &childName) {
+                break;
+            }
+        }
+    }
+    // Attributes `getattr` reports for an inode, `None` when the node is gone.
+    fn getCurrentAttr(&mut self, inode: u64) -> Option<FileAttr> {
+        if inode == CONTROL_FILE_INODE {
+            return self.attrs.get(&inode).cloned();
+        }
+        let (currentPathType, fullPath) = self.getCurrentPathType(inode);
+        let fullPath = fullPath.to_string();
+        let isFile = match currentPathType {
+            GithubVirtualFileSystemPath::FilePath => true,
+            _ => false,
+        };
+        if isFile || self.getClonePath(&fullPath).is_some() {
+            return self.lookupPath(fullPath);
+        }
+        // account and repository directories keep the times and link counts they were
+        // listed with
+        let mut attr = match self.attrs.get(&inode) {
+            Some(attr) => FileAttr { kind: FileType::Directory, perm: 0o755, size: 0, blocks: 0, ..*attr },
+            None => attributes::nodeAttr(inode, FileType::Directory, 0o755, 0, SystemTime::now(), self.config.owner()),
+        };
+        // the root: `.` and its parent, plus the `..` of every account looked up so far
+        if inode == 1 {
+            attr.nlink = 2 + self.getInodesPerType().usersInodes.len() as u32;
+        }
+        Some(attr)
     }
 }
 
 impl Filesystem for GithubVirtualFileSystem {
     fn getattr(&mut self, _req: &Request, _ino: u64, reply: ReplyAttr) {
        // println!("getattr(ino={})", _ino);
-        let ts = SystemTime::now();
-        let attr = FileAttr {
-            ino: _ino,
-            size: 0,
-            blocks: 0,
-            atime: ts,
-            mtime: ts,
-            ctime: ts,
-            crtime: ts,
-            kind: FileType::Directory,
-            perm: 0o755,
-            nlink: 0,
-            uid: 0,
-            gid: 0,
-            rdev: 0,
-            flags: 0,
-            blksize: 0,
+        match self.getCurrentAttr(_ino) {
+            Some(attr) => reply.attr(&Duration::new(0,0), &attr),
+            None => reply.error(ENOENT),
+        }
+    }
+    fn setattr(
+        &mut self,
+        _req: &Request,
+        _ino: u64,
+        _mode: Option<u32>,
+        _uid: Option<u32>,
+        _gid: Option<u32>,
+        _size: Option<u64>,
+        _atime: Option<fuser::TimeOrNow>,
+        _mtime: Option<fuser::TimeOrNow>,
+        _ctime: Option<SystemTime>,
+        _fh: Option<u64>,
+        _crtime: Option<SystemTime>,
+        _chgtime: Option<SystemTime>,
+        _bkuptime: Option<SystemTime>,
+        _flags: Option<u32>,
+        reply: ReplyAttr,
+    ) {
+        // truncating the control file (`echo ... > .control`) is a no-op
+        let (currentPathType, fullPath) = self.getCurrentPathType(_ino);
+        let fullPath = fullPath.to_string();
+        if let GithubVirtualFileSystemPath::ControlPath = currentPathType {
+            reply.attr(&Duration::new(0,0), self.attrs.get(&_ino).unwrap());
+            return;
+        }
+        let isFile = match currentPathType {
+            GithubVirtualFileSystemPath::FilePath => true,
+            _ => false,
+        };
+        if !isFile && self.getClonePath(&fullPath).is_none() {
+            reply.error(EROFS);
+            return;
+        }
+        if let Some(viewPath) = self.getViewPath(&fullPath) {
+            let result = match _size {
+                Some(size) => self.views.truncate(&mut self.trees, &viewPath, size),
+                None => Ok(()),
+            };
+            match result.map(|_| self.lookupPath(fullPath)) {
+                Ok(Some(attr)) => reply.attr(&Duration::new(0,0), &attr),
+                Ok(None) => reply.error(ENOENT),
+                Err(error) => reply.error(error),
+            }
+            return;
+        }
+        let result = match self.getWriteTarget(&fullPath) {
+            Ok(Ok(clonePath)) => passthrough::setAttributes(&clonePath, _mode, _size)
+                .map_err(|error| passthrough::errorCode(&error)),
+            Ok(Err(location)) => self.trees.setAttributes(&location, _mode, _size),
+            Err(error) => Err(error),
         };
-        let ttl = Duration::new(0,0);
-        reply.attr(&ttl, &attr);
+        if let Err(error) = result {
+            reply.error(error);
+            return;
+        }
+        match self.lookupPath(fullPath) {
+            Some(attr) => reply.attr(&Duration::new(0,0), &attr),
+            None => reply.error(ENOENT),
+        }
+    }
+    fn write(
+        &mut self,
+        _req: &Request,
+        _ino: u64,
+        _fh: u64,
+        _offset: i64,
+        _data: &[u8],
+        _write_flags: u32,
+        _flags: i32,
+        _lock_owner: Option<u64>,
+        reply: ReplyWrite,
+    ) {
+        match self.getCurrentPathType(_ino).0 {
+            GithubVirtualFileSystemPath::ControlPath => {
+                let commands = String::from_utf8_lossy(_data).to_string();
+                for command in commands.lines() {
+                    if let Err(error) = self.runControlCommand(command) {
+                        reply.error(error);
+                        return;
+                    }
+                }
+                reply.written(_data.len() as u32);
+            },
+            _ => {
+                let fullPath = self.getCurrentPathType(_ino).1.to_string();
+                let result = match self.getViewPath(&fullPath) {
+                    Some(viewPath) => self.views.write(&mut self.trees, &viewPath, _offset, _data),
+                    None => match self.getWriteTarget(&fullPath) {
+                        Ok(Ok(clonePath)) => {
+                            // keeps the clone recently used for the storage quota
+                            if let Some(fullRepositoryName) = self.getCloneRepository(&fullPath) {
+                                self.cloneStore.touch(&fullRepositoryName);
+                            }
+                            passthrough::writeAt(&clonePath, _offset, _data).map_err(|error| passthrough::errorCode(&error))
+                        },
+                        Ok(Err(location)) => self.trees.write(&location, _offset, _data),
+                        Err(error) => Err(error),
+                    },
+                };
+                match result {
+                    Ok(()) => reply.written(_data.len() as u32),
+                    Err(error) => reply.error(error),
+                }
+            },
+        }
+    }
+    fn read(
+        &mut self,
+        _req: &Request,
+        _ino: u64,
+        _fh: u64,
+        _offset: i64,
+        _size: u32,
+        _flags: i32,
+        _lock_owner: Option<u64>,
+        reply: ReplyData,
+    ) {
+        let fullPath = self.getCurrentPathType(_ino).1.to_string();
+        let result = match self.getClonePath(&fullPath) {
+            Some(clonePath) => passthrough::readAt(&clonePath, _offset, _size)
+                .map_err(|error| passthrough::errorCode(&error)),
+            None => match self.locateTree(&fullPath) {
+                Some(location) => self.trees.read(&location, _offset, _size),
+                None => match views::parseViewPath(&fullPath) {
+                    Some(viewPath) => self.views.read(&mut self.trees, &viewPath, _offset, _size),
+                    None => Err(ENOENT),
+                },
+            },
+        };
+        match result {
+            Ok(data) => reply.data(&data),
+            Err(error) => reply.error(error),
+        }
     }
     fn readlink(&mut self, _req: &Request, _ino: u64, reply: ReplyData) {
        // println!("readlink(_ino={})", _ino);
-        let (currentPathType, fullRepositoryName) = self.getCurrentPathType(_ino);
-        let homeUser = match env::home_dir() {
-            Some(path) => path.display().to_string(),
-            None => ".".to_owned(),
+        let fullPath = self.getCurrentPathType(_ino).1.to_string();
+        let target = match self.getClonePath(&fullPath) {
+            Some(clonePath) => fs::read_link(clonePath)
+                .map(|target| target.as_os_str().to_string_lossy().as_bytes().to_vec())
+                .map_err(|error| passthrough::errorCode(&error)),
+            None => match self.locateTree(&fullPath) {
+                Some(location) => self.trees.readlink(&location),
+                None => match views::parseViewPath(&fullPath) {
+                    Some(viewPath) => self.views.readlink(&mut self.trees, &viewPath),
+                    None => Err(EINVAL),
+                },
+            },
         };
-        let pathToPersist = homeUser + &"/.config/gh_mount/".to_owned() + &fullRepositoryName.to_owned();
-        reply.data(pathToPersist.as_bytes());
+        match target {
+            Ok(target) => reply.data(&target),
+            Err(error) => reply.error(error),
+        }
     }
     fn open(&mut self, _req: &Request, _ino: u64, _flags: i32, reply: ReplyOpen) {
        // println!("open(_ino={}, _flags={})", _ino, _flags);
-        reply.opened(_ino, _flags as u32);
+        // files of the special directories that are costly to generate are generated here
+        let fullPath = self.getCurrentPathType(_ino).1.to_string();
+        let result = match self.getCloneRepository(&fullPath) {
+            // clones with open files are not evicted
+            Some(fullRepositoryName) => {
+                self.cloneStore.opened(&fullRepositoryName);
+                Ok(0)
+            },
+            None => match self.getViewPath(&fullPath) {
+                Some(viewPath) => self.views.open(&mut self.trees, &viewPath),
+                None => Ok(0),
+            },
+        };
+        match result {
+            Ok(openFlags) => reply.opened(_ino, openFlags),
+            Err(error) => reply.error(error),
+        }
+    }
+    fn flush(&mut self, _req: &Request, _ino: u64, _fh: u64, _lock_owner: u64, reply: ReplyEmpty) {
+        // files of the special directories that take input send it when closed
+        let fullPath = self.getCurrentPathType(_ino).1.to_string();
+        let result = match self.getViewPath(&fullPath) {
+            Some(viewPath) => self.views.flush(&viewPath),
+            None => Ok(()),
+        };
+        match result {
+            Ok(()) => reply.ok(),
+            Err(error) => reply.error(error),
+        }
+    }
+    fn release(
+        &mut self,
+        _req: &Request,
+        _ino: u64,
+        _fh: u64,
+        _flags: i32,
+        _lock_owner: Option<u64>,
+        _flush: bool,
+        reply: ReplyEmpty,
+    ) {
+        let fullPath = self.getCurrentPathType(_ino).1.to_string();
+        if let Some(fullRepositoryName) = self.getCloneRepository(&fullPath) {
+            self.cloneStore.released(&fullRepositoryName);
+        }
+        let result = match self.getViewPath(&fullPath) {
+            Some(viewPath) => self.views.release(&viewPath),
+            None => Ok(()),
+        };
+        match result {
+            Ok(()) => reply.ok(),
+            Err(error) => reply.error(error),
+        }
     }
     fn lookup(&mut self, _req: &Request, parent: u64, name: &OsStr, reply: ReplyEntry) {
        // println!("lookup(parent={}, name={})", parent, name.to_str().unwrap());
@@ -350,6 +814,14 @@ impl Filesystem for GithubVirtualFileSystem {
         let ts = time::now().to_timespec();
         let (currentPathType, fullRepositoryName) = self.getCurrentPathType(parent);
         let inode = match currentPathType {
+            // account views and `repo@{date}`
+            GithubVirtualFileSystemPath::UserPath if views::isViewPath(&(fullRepositoryName.to_string() + "/" + &name.to_string_lossy())) => {
+                let fullPath = fullRepositoryName.to_string() + "/" + &name.to_string_lossy();
+                match self.lookupPath(fullPath) {
+                    Some(attr) => attr.ino,
+                    None => 0,
+                }
+            },
             GithubVirtualFileSystemPath::UserPath => {
                 let mut desiredInode = 0;
                 let repositories = self.getRepositoriesFromUser(fullRepositoryName);
@@ -358,18 +830,24 @@ impl Filesystem for GithubVirtualFileSystem {
                     let isSameRepo = fullpathSplitted[1].eq(&name.to_str().unwrap().to_string());
                     if !isSameRepo {  continue;   };
                     desiredInode = *inode;
+                    if self.config.shouldAutoClone(repositoryName) {
+                        if let Err(error) = self.cloneStore.materialize(&self.config, &self.storage, repositoryName) {
+                            eprintln!("gh-mount: auto clone of {} failed: {}", repositoryName, error);
+                        }
+                    }
                     break;
                 }
                 desiredInode
             },
-            GithubVirtualFileSystemPath::RepositoryPath => {
-                let exampleInode = 0;
-                exampleInode
-            },
-            GithubVirtualFileSystemPath::FilePath => {
-                let exampleInode = 0;
-                exampleInode
+            GithubVirtualFileSystemPath::RepositoryPath | GithubVirtualFileSystemPath::FilePath => {
+                let fullPath = fullRepositoryName.to_string() + "/" + &name.to_string_lossy();
+                match self.lookupPath(fullPath) {
+                    Some(attr) => attr.ino,
+                    None => 0,
+                }
             },
+            GithubVirtualFileSystemPath::ControlPath => 0,
+            GithubVirtualFileSystemPath::None if name == CONTROL_FILE_NAME => CONTROL_FILE_INODE,
             GithubVirtualFileSystemPath::None => {
                 let repositories = self.getRepositoriesFromUser(fullRepositoryName);
                 self.addUser(name.to_str().unwrap());
@@ -385,32 +863,20 @@ impl Filesystem for GithubVirtualFileSystem {
             },
         };
        
+        let (currentPathType, fullRepositoryName) = self.getCurrentPathType(inode);
+        if let GithubVirtualFileSystemPath::RepositoryPath = currentPathType {
+            // a cloned repository is served from its clone, in place
+            let fullRepositoryName = fullRepositoryName.to_string();
+            if self.cloneStore.isCloned(&fullRepositoryName) {
+                self.cloneStore.touch(&fullRepositoryName);
+                self.fetcher.fetchIfStale(&fullRepositoryName);
+                self.lookupInClone(fullRepositoryName);
+            }
+        }
         match self.attrs.get(&inode) {
             Some(attr) => {
-                let (currentPathType, fullRepositoryName) = self.getCurrentPathType(inode);
                 let ttl = Duration::new(0,0);
-                let homeUser = match env::home_dir() {
-                    Some(path) => path.display().to_string(),
-                    None => ".".to_owned(),
-                };
-                let pathToPersist = homeUser + &"/.config/gh_mount/".to_owned() + &fullRepositoryName.to_owned();
-                let hasToBeASymlink = match currentPathType {
-                    GithubVirtualFileSystemPath::UserPath => false,
-                    GithubVirtualFileSystemPath::RepositoryPath => {
-                        let pathAlreadyExists = Path::new(&pathToPersist).exists();
-                        pathAlreadyExists
-                    },
-                    GithubVirtualFileSystemPath::FilePath => false,
-                    GithubVirtualFileSystemPath::None => false,
-                };
-                if !hasToBeASymlink {
-                    reply.entry(&ttl, attr, 0);
-                    return;
-                }
-                let mut newAttr = attr.clone();
-                newAttr.kind = FileType::Symlink;
-           
-                reply.entry(&ttl, &newAttr, 0);
+                reply.entry(&ttl, attr, 0);
             }
             None => reply.error(ENOENT),
         };
@@ -447,15 +913,38 @@ impl Filesystem for GithubVirtualFileSystem {
                     }
                 }
             },
-            GithubVirtualFileSystemPath::RepositoryPath => {
-                let files = self.getFilesFromRepo(fullRepositoryName);
-                for (filename, inode) in files.iter() {
-                    if _offset == 0 {
-                        reply.add(*inode, (*inode) as i64, FileType::Directory, &Path::new(filename));
+            GithubVirtualFileSystemPath::RepositoryPath | GithubVirtualFileSystemPath::FilePath if self.getClonePath(fullRepositoryName).is_some() => {
+                let fullPath = fullRepositoryName.to_string();
+                let children = match passthrough::listDirectory(&self.getClonePath(&fullPath).unwrap()) {
+                    Ok(children) => children,
+                    Err(error) => {
+                        reply.error(passthrough::errorCode(&error));
+                        return;
+                    }
+                };
+                self.addChildren(&mut reply, &fullPath, children, _offset);
+            },
+            GithubVirtualFileSystemPath::RepositoryPath | GithubVirtualFileSystemPath::FilePath => {
+                let fullPath = fullRepositoryName.to_string();
+                let children = match self.locateTree(&fullPath) {
+                    Some(location) => self.trees.list(&location),
+                    None => match views::parseViewPath(&fullPath) {
+                        Some(viewPath) => self.views.list(&mut self.trees, &viewPath),
+                        None => Err(EIO),
+                    },
+                };
+                match children {
+                    Ok(children) => self.addChildren(&mut reply, &fullPath, children, _offset),
+                    Err(error) => {
+                        reply.error(error);
+                        return;
                     }
                 }
             },
-            GithubVirtualFileSystemPath::FilePath => {},
+            GithubVirtualFileSystemPath::ControlPath => {
+                reply.error(libc::ENOTDIR);
+                return;
+            },
             GithubVirtualFileSystemPath::None => {
                 reply.error(EPERM);
                 return;
@@ -465,56 +954,305 @@ impl Filesystem for GithubVirtualFileSystem {
     }
     fn access(&mut self, _req: &Request, _ino: u64, _mask: i32, reply: ReplyEmpty) {
        // println!("access(ino={}, _mask={})", _ino, _mask);
-        let (currentPathType, fullRepositoryName) = self.getCurrentPathType(_ino);
-        match currentPathType {
-            GithubVirtualFileSystemPath::UserPath => {
-      
-            },
-            GithubVirtualFileSystemPath::RepositoryPath => {
-                let homeUser = match env::home_dir() {
-                    Some(path) => path.display().to_string(),
-                    None => ".".to_owned(),
-                };
-                let pathToPersist = homeUser + &"/.config/gh_mount/".to_owned() + &fullRepositoryName.to_owned();
-                let createPersistPathArgs = [
-                    "-p", &pathToPersist
-                ];
-                Command::new("mkdir")
-                    .args(createPersistPathArgs)
-                    .output()
-                    .expect(format!("Error when running: mkdir {:?}", createPersistPathArgs.join(" ").as_str()).as_str());
-
-                let args = [
-                    "repo", "clone", fullRepositoryName, "--", &pathToPersist
-                ];
-               // println!("args={:?}", args);
-                let listOutput = Command::new("gh")
-                    .args(args)
-                    .output()
-                    .expect(format!("Error when running: gh {:?}", args.join(" ").as_str()).as_str());
-                let stdout = String::from_utf8(listOutput.stdout).unwrap();
-                let mut pathAttr: FileAttr = *self.attrs.get(&_ino).unwrap();
-                pathAttr.kind = FileType::Symlink;
-                self.attrs.insert(_ino, pathAttr);
+        if !self.attrs.contains_key(&_ino) {
+            reply.error(ENOENT);
+            return;
+        }
+        let wantsToWrite = _mask & libc::W_OK != 0;
+        // inside a repository edits go to its clone or overlay and account directories
+        // take `mkdir` to create repositories, so their permission bits decide
+        match self.getCurrentPathType(_ino).0 {
+            GithubVirtualFileSystemPath::None if wantsToWrite => {
+                reply.error(EROFS);
+                return;
             },
-            GithubVirtualFileSystemPath::FilePath => {},
             GithubVirtualFileSystemPath::None => {
-       
+                reply.ok();
+                return;
             },
+            _ => (),
+        }
+        match self.getCurrentAttr(_ino) {
+            Some(ref attr) if attributes::isAccessAllowed(attr, _req.uid(), _req.gid(), _mask) => reply.ok(),
+            Some(_) => reply.error(EACCES),
+            None => reply.error(ENOENT),
+        }
+    }
+    fn create(
+        &mut self,
+        _req: &Request,
+        parent: u64,
+        name: &OsStr,
+        mode: u32,
+        _umask: u32,
+        _flags: i32,
+        reply: ReplyCreate,
+    ) {
+        let fullPath = match self.getChildPath(parent, name) {
+            Some(fullPath) => fullPath,
+            None => {
+                reply.error(EROFS);
+                return;
+            }
         };
-        reply.ok()
+        let result = match self.getViewPath(&fullPath) {
+            Some(viewPath) => self.views.create(&viewPath),
+            None => match self.getWriteTarget(&fullPath) {
+                Ok(Ok(clonePath)) => passthrough::createFile(&clonePath, mode & !_umask)
+                    .map_err(|error| passthrough::errorCode(&error)),
+                Ok(Err(location)) => self.trees.create(&location, mode & !_umask),
+                Err(error) => Err(error),
+            },
+        };
+        if let Err(error) = result {
+            reply.error(error);
+            return;
+        }
+        let cloneRepository = self.getCloneRepository(&fullPath);
+        match self.lookupPath(fullPath) {
+            // the reply takes FOPEN_* flags, not the open(2) ones (O_EXCL from `cp` would
+            // be read as FOPEN_PASSTHROUGH)
+            Some(attr) => {
+                if let Some(fullRepositoryName) = cloneRepository {
+                    self.cloneStore.opened(&fullRepositoryName);
+                }
+                reply.created(&Duration::new(0,0), &attr, 0, attr.ino, 0)
+            },
+            None => reply.error(EIO),
+        }
+    }
+    fn mkdir(&mut self, _req: &Request, parent: u64, name: &OsStr, mode: u32, _umask: u32, reply: ReplyEntry) {
+        if let (GithubVirtualFileSystemPath::UserPath, owner) = self.getCurrentPathType(parent) {
+            let owner = owner.to_string();
+            match self.createRepository(&owner, &name.to_string_lossy()) {
+                Ok(attr) => reply.entry(&Duration::new(0,0), &attr, 0),
+                Err(error) => reply.error(error),
+            }
+            return;
+        }
+        let fullPath = match self.getChildPath(parent, name) {
+            Some(fullPath) => fullPath,
+            None => {
+                reply.error(EROFS);
+                return;
+            }
+        };
+        if let Some(viewPath) = self.getViewPath(&fullPath) {
+            let result = self.views.mkdir(&mut self.trees, &viewPath);
+            match result.map(|createdPath| self.lookupPath(createdPath)) {
+                Ok(Some(attr)) => reply.entry(&Duration::new(0,0), &attr, 0),
+                Ok(None) => reply.error(EIO),
+                Err(error) => reply.error(error),
+            }
+            return;
+        }
+        let result = match self.getWriteTarget(&fullPath) {
+            Ok(Ok(clonePath)) => passthrough::makeDirectory(&clonePath, mode & !_umask)
+                .map_err(|error| passthrough::errorCode(&error)),
+            Ok(Err(location)) => self.trees.mkdir(&location, mode & !_umask),
+            Err(error) => Err(error),
+        };
+        if let Err(error) = result {
+            reply.error(error);
+            return;
+        }
+        match self.lookupPath(fullPath) {
+            Some(attr) => reply.entry(&Duration::new(0,0), &attr, 0),
+            None => reply.error(EIO),
+        }
+    }
+    fn unlink(&mut self, _req: &Request, parent: u64, name: &OsStr, reply: ReplyEmpty) {
+        let fullPath = match self.getChildPath(parent, name) {
+            Some(fullPath) => fullPath,
+            None => {
+                reply.error(EROFS);
+                return;
+            }
+        };
+        let result = match self.getWriteTarget(&fullPath) {
+            Ok(Ok(clonePath)) => fs::remove_file(clonePath).map_err(|error| passthrough::errorCode(&error)),
+            Ok(Err(location)) => self.trees.unlink(&location),
+            Err(error) => Err(error),
+        };
+        match result {
+            Ok(()) => reply.ok(),
+            Err(error) => reply.error(error),
+        }
+    }
+    fn rmdir(&mut self, _req: &Request, parent: u64, name: &OsStr, reply: ReplyEmpty) {
+        let fullPath = match self.getChildPath(parent, name) {
+            Some(fullPath) => fullPath,
+            None => {
+                reply.error(EROFS);
+                return;
+            }
+        };
+        if let Some(viewPath) = self.getViewPath(&fullPath) {
+            match self.views.rmdir(&mut self.trees, &viewPath) {
+                Ok(()) => reply.ok(),
+                Err(error) => reply.error(error),
+            }
+            return;
+        }
+        let result = match self.getWriteTarget(&fullPath) {
+            Ok(Ok(clonePath)) => fs::remove_dir(clonePath).map_err(|error| passthrough::errorCode(&error)),
+            Ok(Err(location)) => self.trees.rmdir(&location),
+            Err(error) => Err(error),
+        };
+        match result {
+            Ok(()) => reply.ok(),
+            Err(error) => reply.error(error),
+        }
+    }
+    fn rename(
+        &mut self,
+        _req: &Request,
+        parent: u64,
+        name: &OsStr,
+        newparent: u64,
+        newname: &OsStr,
+        _flags: u32,
+        reply: ReplyEmpty,
+    ) {
+        let (fromPath, toPath) = match (self.getChildPath(parent, name), self.getChildPath(newparent, newname)) {
+            (Some(fromPath), Some(toPath)) => (fromPath, toPath),
+            _ => {
+                reply.error(EROFS);
+                return;
+            }
+        };
+        let result = match (self.getWriteTarget(&fromPath), self.getWriteTarget(&toPath)) {
+            (Ok(Ok(fromClonePath)), Ok(Ok(toClonePath))) => fs::rename(fromClonePath, toClonePath)
+                .map_err(|error| passthrough::errorCode(&error)),
+            (Ok(Err(fromLocation)), Ok(Err(toLocation))) => self.trees.rename(&fromLocation, &toLocation),
+            (Err(error), _) | (_, Err(error)) => Err(error),
+            _ => Err(libc::EXDEV),
+        };
+        match result {
+            Ok(()) => {
+                self.renameInodes(&fromPath, &toPath);
+                reply.ok();
+            },
+            Err(error) => reply.error(error),
+        }
+    }
+    fn getxattr(&mut self, _req: &Request, _ino: u64, name: &OsStr, size: u32, reply: ReplyXattr) {
+        // `ls -l` asks for `security.selinux` and ACLs on every file
+        let name = name.to_string_lossy().to_string();
+        if !name.starts_with(attributes::ATTRIBUTE_PREFIX) {
+            reply.error(NO_ATTRIBUTE);
+            return;
+        }
+        let fullPath = self.getCurrentPathType(_ino).1.to_string();
+        let extendedAttributes = self.getExtendedAttributes(&fullPath, Some(&name));
+        let value = match extendedAttributes.iter().find(|&&(attributeName, _)| name == attributeName) {
+            Some(&(_, ref value)) => value.as_bytes(),
+            None => {
+                reply.error(NO_ATTRIBUTE);
+                return;
+            }
+        };
+        replyXattr(reply, value, size);
+    }
+    fn listxattr(&mut self, _req: &Request, _ino: u64, size: u32, reply: ReplyXattr) {
+        let fullPath = self.getCurrentPathType(_ino).1.to_string();
+        let mut names = Vec::new();
+        for (name, _) in self.getExtendedAttributes(&fullPath, None) {
+            names.extend_from_slice(name.as_bytes());
+            names.push(0);
+        }
+        replyXattr(reply, &names, size);
+    }
+    fn setxattr(
+        &mut self,
+        _req: &Request,
+        _ino: u64,
+        name: &OsStr,
+        value: &[u8],
+        _flags: i32,
+        _position: u32,
+        reply: ReplyEmpty,
+    ) {
+        let fullPath = self.getCurrentPathType(_ino).1.to_string();
+        let result = match self.getViewPath(&fullPath) {
+            Some(viewPath) => self.views.setAttribute(&viewPath, &name.to_string_lossy(), value),
+            None => Err(libc::ENOTSUP),
+        };
+        match result {
+            Ok(()) => reply.ok(),
+            Err(error) => reply.error(error),
+        }
+    }
+}
+
+// A `size` of 0 asks how large the value is.
+fn replyXattr(reply: ReplyXattr, value: &[u8], size: u32) {
+    if size == 0 {
+        reply.size(value.len() as u32);
+    } else if value.len() > size as usize {
+        reply.error(libc::ERANGE);
+    } else {
+        reply.data(value);
     }
 }
 
 fn main() {
-    let mountpoint = match env::args().nth(1) {
-        Some(path) => path,
+    let mut config = MountConfig::load();
+    let arguments = match config.applyArguments(env::args().skip(1)) {
+        Ok(arguments) => arguments,
+        Err(error) => {
+            eprintln!("gh-mount: {}", error);
+            return;
+        }
+    };
+    let storage = match Storage::resolve(&config) {
+        Ok(storage) => storage,
+        Err(error) => {
+            eprintln!("gh-mount: {}", error);
+            return;
+        }
+    };
+    let mountpoint = match arguments.first() {
+        Some(command) if command == "clone" => {
+            let mut cloneStore = CloneStore::load(&config, &storage);
+            let mut hasFailed = false;
+            for fullRepositoryName in arguments.iter().skip(1) {
+                match cloneStore.materialize(&config, &storage, fullRepositoryName) {
+                    Ok(()) => println!("cloned {}", fullRepositoryName),
+                    Err(error) => {
+                        eprintln!("gh-mount: clone of {} failed: {}", fullRepositoryName, error);
+                        hasFailed = true;
+                    },
+                }
+            }
+            // the other repositories are still cloned, but scripts see the failure
+            if hasFailed {
+                process::exit(1);
+            }
+            return;
+        },
+        Some(command) if command == "publish" => {
+            let mut trees = Trees::new(&storage.blobsDirectory, &storage.overlaysDirectory, config.lastCommitTimes, config.owner());
+            let publishArguments: Vec<&str> = arguments.iter().skip(1).map(|argument| argument.as_str()).collect();
+            match publish::publishCommand(&mut trees, &publishArguments) {
+                Ok(commitSha) => println!("published {}", commitSha),
+                Err(error) => {
+                    eprintln!("gh-mount: publish failed: {}", error.message());
+                    process::exit(1);
+                },
+            }
+            return;
+        },
+        Some(path) => path.clone(),
         None => {
            // println!("Usage: {} <MOUNTPOINT>", env::args().nth(0).unwrap());
             return;
         }
     };
-    let fs = GithubVirtualFileSystem::new();
+    let fs = GithubVirtualFileSystem::new(config, storage);
+    if let Some(period) = fs.config.fetchSchedule {
+        fs.fetcher.startSchedule(period);
+    }
 
     fuser::mount2(fs, &mountpoint, &[]);
 }
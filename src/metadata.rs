@@ -0,0 +1,45 @@
+use fuser::FileType;
+use libc::c_int;
+
+use views::{ViewNode, ViewPath, Views};
+
+// `.gh/`: what GitHub knows about the repository, as files. They are regenerated
+// from the API whenever the cached response is older than CACHE_TTL.
+
+const METADATA_FILES: &[&str] = &["contributors.json", "default-branch", "description", "languages.json", "meta.json", "topics"];
+
+fn render(views: &mut Views, fullRepositoryName: &str, name: &str) -> Result<Option<Vec<u8>>, c_int> {
+    let repositoryPath = format!("repos/{}", fullRepositoryName);
+    // one value per line, like `cat` expects
+    let mut lines = |jq: &str| {
+        views.gh(&["api", &repositoryPath, "--jq", jq]).map(|output| match output.len() {
+            0 => Vec::new(),
+            _ => (output + "\n").into_bytes(),
+        })
+    };
+    let contents = match name {
+        "meta.json" => return views.ghFile(&["api", &repositoryPath]).map(Some),
+        "description" => lines(r#".description // """#)?,
+        "topics" => lines(".topics[]")?,
+        "default-branch" => lines(".default_branch")?,
+        "languages.json" => return views.ghFile(&["api", &format!("{}/languages", repositoryPath)]).map(Some),
+        // the top 100, paginating would output one JSON array per page
+        "contributors.json" => {
+            return views.ghFile(&["api", &format!("{}/contributors?per_page=100", repositoryPath)]).map(Some);
+        },
+        _ => return Ok(None),
+    };
+    Ok(Some(contents))
+}
+
+pub fn resolve(views: &mut Views, path: &ViewPath) -> Result<Option<ViewNode>, c_int> {
+    match path.rest.as_slice() {
+        [] => Ok(Some(ViewNode::Directory)),
+        [ref name] => Ok(render(views, &path.repository, name)?.map(ViewNode::File)),
+        _ => Ok(None),
+    }
+}
+
+pub fn list(_views: &mut Views, _path: &ViewPath) -> Result<Vec<(String, FileType)>, c_int> {
+    Ok(METADATA_FILES.iter().map(|name| (name.to_string(), FileType::RegularFile)).collect())
+}
@@ -0,0 +1,182 @@
+use fuser::FileType;
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use passthrough;
+
+// Prefix of the marker left in the upper directory for a deleted remote entry, the
+// same convention as aufs/overlayfs: deleting `a/b` creates `upper/a/.wh.b`.
+pub const WHITEOUT_PREFIX: &str = ".wh.";
+// Marks an upper directory that replaces the remote one instead of merging with it
+// (a directory that was removed and created again).
+pub const OPAQUE_MARKER: &str = ".wh..wh..opq";
+
+// Local edits of one repository at one ref. Files that were written, created or
+// renamed live below `upper/`; `base` is the commit the edits were made on top of.
+pub struct Overlay {
+    pub directory: PathBuf,
+}
+
+impl Overlay {
+    pub fn new(overlaysDirectory: &Path, fullRepositoryName: &str, reference: &str) -> Overlay {
+        let encodedReference = reference.replace("%", "%25").replace("/", "%2F");
+        Overlay {
+            directory: overlaysDirectory.join(fullRepositoryName).join(encodedReference),
+        }
+    }
+    pub fn upperPath(&self, relativePath: &str) -> PathBuf {
+        let mut upperPath = self.directory.join("upper");
+        for pathPart in relativePath.split("/").filter(|pathPart| pathPart.len() > 0) {
+            upperPath.push(pathPart);
+        }
+        upperPath
+    }
+    pub fn baseCommit(&self) -> Option<String> {
+        fs::read_to_string(self.directory.join("base"))
+            .ok()
+            .map(|baseCommit| baseCommit.trim().to_string())
+            .filter(|baseCommit| baseCommit.len() > 0)
+    }
+    pub fn setBaseCommit(&self, commitSha: &str) -> Result<(), String> {
+        fs::create_dir_all(&self.directory)
+            .and_then(|_| fs::write(self.directory.join("base"), commitSha))
+            .map_err(|error| format!("could not write {}: {}", self.directory.join("base").display(), error))
+    }
+    // Directories without files or whiteouts below them don't count: they are the
+    // parents copied up for an edit that was undone, and git can't publish them.
+    pub fn hasChanges(&self) -> bool {
+        containsFiles(&self.upperPath(""))
+    }
+    // Drops every local edit, the next modification starts a new overlay.
+    pub fn discard(&self) -> Result<(), String> {
+        match fs::remove_dir_all(&self.directory) {
+            Ok(()) => Ok(()),
+            Err(ref error) if error.kind() == ::std::io::ErrorKind::NotFound => Ok(()),
+            Err(error) => Err(format!("could not remove {}: {}", self.directory.display(), error)),
+        }
+    }
+    // Whether the remote entry at `relativePath` was deleted locally, either itself or
+    // through one of its parents.
+    pub fn isHidden(&self, relativePath: &str) -> bool {
+        let pathParts: Vec<&str> = relativePath.split("/").filter(|pathPart| pathPart.len() > 0).collect();
+        let mut parentPath = self.upperPath("");
+        for pathPart in pathParts.iter() {
+            if parentPath.join(OPAQUE_MARKER).exists() {
+                return true;
+            }
+            if parentPath.join(WHITEOUT_PREFIX.to_string() + pathPart).exists() {
+                return true;
+            }
+            parentPath.push(pathPart);
+        }
+        false
+    }
+    pub fn isOpaque(&self, relativePath: &str) -> bool {
+        self.upperPath(relativePath).join(OPAQUE_MARKER).exists()
+    }
+    pub fn whiteout(&self, relativePath: &str) -> Result<(), i32> {
+        let whiteoutPath = self.whiteoutPath(relativePath);
+        fs::create_dir_all(whiteoutPath.parent().unwrap())
+            .and_then(|_| fs::write(&whiteoutPath, ""))
+            .map_err(|error| passthrough::errorCode(&error))
+    }
+    // Returns whether there was a whiteout to remove.
+    pub fn clearWhiteout(&self, relativePath: &str) -> bool {
+        fs::remove_file(self.whiteoutPath(relativePath)).is_ok()
+    }
+    pub fn markOpaque(&self, relativePath: &str) -> Result<(), i32> {
+        fs::write(self.upperPath(relativePath).join(OPAQUE_MARKER), "")
+            .map_err(|error| passthrough::errorCode(&error))
+    }
+    // Names deleted locally from the directory at `relativePath`.
+    pub fn whiteouts(&self, relativePath: &str) -> HashSet<String> {
+        let mut whiteouts = HashSet::new();
+        if let Ok(children) = fs::read_dir(self.upperPath(relativePath)) {
+            for child in children.filter_map(|child| child.ok()) {
+                let childName = child.file_name().to_string_lossy().to_string();
+                if childName.starts_with(WHITEOUT_PREFIX) && childName != OPAQUE_MARKER {
+                    whiteouts.insert(childName[WHITEOUT_PREFIX.len()..].to_string());
+                }
+            }
+        }
+        whiteouts
+    }
+    pub fn listUpper(&self, relativePath: &str) -> Vec<(String, FileType)> {
+        match passthrough::listDirectory(&self.upperPath(relativePath)) {
+            Ok(children) => children
+                .into_iter()
+                .filter(|&(ref childName, _)| !childName.starts_with(WHITEOUT_PREFIX))
+                .collect(),
+            Err(_) => Vec::new(),
+        }
+    }
+    fn whiteoutPath(&self, relativePath: &str) -> PathBuf {
+        let upperPath = self.upperPath(relativePath);
+        let name = upperPath.file_name().unwrap().to_string_lossy().to_string();
+        upperPath.with_file_name(WHITEOUT_PREFIX.to_string() + &name)
+    }
+}
+
+fn containsFiles(directory: &Path) -> bool {
+    match fs::read_dir(directory) {
+        Ok(children) => children.filter_map(|child| child.ok()).any(|child| match child.file_type() {
+            Ok(ref fileType) if fileType.is_dir() => containsFiles(&child.path()),
+            _ => true,
+        }),
+        Err(_) => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+    use std::process;
+
+    // An overlay in a directory of its own below the system temporary directory.
+    fn testOverlay(testName: &str) -> Overlay {
+        let overlaysDirectory = env::temp_dir().join(format!("gh-mount-test-{}-{}", process::id(), testName));
+        let _ = fs::remove_dir_all(&overlaysDirectory);
+        Overlay::new(&overlaysDirectory, "cli/cli", "feature/x")
+    }
+
+    #[test]
+    fn createsAndClearsWhiteouts() {
+        let overlay = testOverlay("whiteouts");
+        assert!(overlay.directory.ends_with("cli/cli/feature%2Fx"));
+        assert!(!overlay.hasChanges());
+        overlay.whiteout("docs/old.md").unwrap();
+        assert!(overlay.upperPath("docs").join(".wh.old.md").is_file());
+        assert!(overlay.whiteouts("docs").contains("old.md"));
+        assert!(overlay.listUpper("docs").is_empty());
+        assert!(overlay.hasChanges());
+        assert!(overlay.clearWhiteout("docs/old.md"));
+        assert!(!overlay.clearWhiteout("docs/old.md"));
+        assert!(overlay.whiteouts("docs").is_empty());
+        // the parent copied up for the whiteout doesn't count as a change
+        assert!(!overlay.hasChanges());
+        overlay.discard().unwrap();
+        assert!(!overlay.directory.exists());
+    }
+
+    #[test]
+    fn hidesEntriesBelowWhiteoutsAndOpaqueDirectories() {
+        let overlay = testOverlay("hidden");
+        overlay.whiteout("src").unwrap();
+        assert!(overlay.isHidden("src"));
+        assert!(overlay.isHidden("src/main.rs"));
+        assert!(!overlay.isHidden("srcs"));
+        assert!(!overlay.isHidden("README.md"));
+        fs::create_dir_all(overlay.upperPath("docs")).unwrap();
+        overlay.markOpaque("docs").unwrap();
+        assert!(overlay.isOpaque("docs"));
+        assert!(!overlay.isOpaque("src"));
+        assert!(!overlay.isHidden("docs"));
+        assert!(overlay.isHidden("docs/guide.md"));
+        // the marker is neither a whiteout nor a listed entry
+        assert!(overlay.whiteouts("docs").is_empty());
+        assert!(overlay.listUpper("docs").is_empty());
+        overlay.discard().unwrap();
+    }
+}
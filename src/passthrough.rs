@@ -0,0 +1,101 @@
+use fuser::{FileAttr, FileType};
+use libc::{c_int, EIO};
+use std::fs::{self, File, Metadata, OpenOptions};
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::os::unix::fs::{MetadataExt, OpenOptionsExt, PermissionsExt};
+use std::path::Path;
+use std::time::{Duration, UNIX_EPOCH};
+
+use attributes::Owner;
+
+// Helpers to serve the files of a clone in place: the FUSE handlers resolve the
+// mount path to the path inside the clone and call these with it.
+
+// Times, modes and sizes come from disk, the owner is the one of the whole mount.
+pub fn attrFromMetadata(ino: u64, metadata: &Metadata, owner: Owner) -> FileAttr {
+    let fileType = metadata.file_type();
+    let kind = if fileType.is_dir() {
+        FileType::Directory
+    } else if fileType.is_symlink() {
+        FileType::Symlink
+    } else {
+        FileType::RegularFile
+    };
+    FileAttr {
+        ino: ino,
+        size: metadata.size(),
+        blocks: metadata.blocks(),
+        atime: UNIX_EPOCH + Duration::new(metadata.atime().max(0) as u64, metadata.atime_nsec() as u32),
+        mtime: UNIX_EPOCH + Duration::new(metadata.mtime().max(0) as u64, metadata.mtime_nsec() as u32),
+        ctime: UNIX_EPOCH + Duration::new(metadata.ctime().max(0) as u64, metadata.ctime_nsec() as u32),
+        crtime: metadata.created().unwrap_or(UNIX_EPOCH),
+        kind: kind,
+        perm: (metadata.mode() & 0o7777) as u16,
+        nlink: metadata.nlink() as u32,
+        uid: owner.uid,
+        gid: owner.gid,
+        rdev: metadata.rdev() as u32,
+        flags: 0,
+        blksize: metadata.blksize() as u32,
+    }
+}
+
+pub fn listDirectory(path: &Path) -> io::Result<Vec<(String, FileType)>> {
+    let mut children = Vec::new();
+    for child in fs::read_dir(path)? {
+        let child = child?;
+        let fileType = child.file_type()?;
+        let kind = if fileType.is_dir() {
+            FileType::Directory
+        } else if fileType.is_symlink() {
+            FileType::Symlink
+        } else {
+            FileType::RegularFile
+        };
+        children.push((child.file_name().to_string_lossy().to_string(), kind));
+    }
+    children.sort_by(|first, second| first.0.cmp(&second.0));
+    Ok(children)
+}
+
+pub fn readAt(path: &Path, offset: i64, size: u32) -> io::Result<Vec<u8>> {
+    let mut file = File::open(path)?;
+    file.seek(SeekFrom::Start(offset.max(0) as u64))?;
+    let mut data = Vec::with_capacity(size as usize);
+    file.take(size as u64).read_to_end(&mut data)?;
+    Ok(data)
+}
+
+pub fn writeAt(path: &Path, offset: i64, data: &[u8]) -> io::Result<()> {
+    let mut file = OpenOptions::new().write(true).open(path)?;
+    file.seek(SeekFrom::Start(offset.max(0) as u64))?;
+    file.write_all(data)
+}
+
+pub fn setAttributes(path: &Path, mode: Option<u32>, size: Option<u64>) -> io::Result<()> {
+    if let Some(size) = size {
+        OpenOptions::new().write(true).open(path)?.set_len(size)?;
+    }
+    if let Some(mode) = mode {
+        fs::set_permissions(path, fs::Permissions::from_mode(mode & 0o7777))?;
+    }
+    Ok(())
+}
+
+pub fn createFile(path: &Path, mode: u32) -> io::Result<()> {
+    OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .mode(mode & 0o7777)
+        .open(path)
+        .map(|_| ())
+}
+
+pub fn makeDirectory(path: &Path, mode: u32) -> io::Result<()> {
+    fs::create_dir(path)?;
+    fs::set_permissions(path, fs::Permissions::from_mode(mode & 0o7777))
+}
+
+pub fn errorCode(error: &io::Error) -> c_int {
+    error.raw_os_error().unwrap_or(EIO)
+}
@@ -0,0 +1,191 @@
+use std::fs;
+use std::io;
+use std::os::unix::fs::PermissionsExt;
+
+use github::{self, NewTreeEntry, TreeEntry};
+use overlay::{Overlay, OPAQUE_MARKER, WHITEOUT_PREFIX};
+use tree::{TreeRoot, Trees};
+
+const DEFAULT_MESSAGE: &str = "Update files from gh mount";
+
+pub enum PublishError {
+    // the target branch moved since the commit the local edits were made on
+    Conflict(String),
+    Failed(String),
+}
+
+impl PublishError {
+    pub fn message(&self) -> &str {
+        match *self {
+            PublishError::Conflict(ref message) => message,
+            PublishError::Failed(ref message) => message,
+        }
+    }
+}
+
+// `owner/repo[@ref] [branch [message...]]`, shared by `gh mount publish` and the
+// `publish` line of the control file. Returns the new commit.
+pub fn publishCommand(trees: &mut Trees, arguments: &[&str]) -> Result<String, PublishError> {
+    let (fullRepositoryName, reference) = match arguments.first() {
+        Some(target) => match target.find("@") {
+            Some(index) => (target[..index].to_string(), Some(target[index + 1..].to_string())),
+            None => (target.to_string(), None),
+        },
+        None => return Err(PublishError::Failed("usage: publish owner/repo[@ref] [branch [message...]]".to_string())),
+    };
+    if fullRepositoryName.split("/").count() != 2 {
+        return Err(PublishError::Failed(format!("{} is not an owner/repo name", fullRepositoryName)));
+    }
+    let root = match reference {
+        Some(reference) => TreeRoot {
+            repository: fullRepositoryName,
+            reference: reference,
+        },
+        None => match trees.locate(&fullRepositoryName) {
+            Some(location) => location.root,
+            None => return Err(PublishError::Failed(format!("could not find {}", fullRepositoryName))),
+        },
+    };
+    let branch = arguments.get(1).map(|branch| branch.to_string()).unwrap_or(root.reference.clone());
+    let message = match arguments.len() > 2 {
+        true => arguments[2..].join(" "),
+        false => DEFAULT_MESSAGE.to_string(),
+    };
+    publish(trees, &root, &branch, &message)
+}
+
+// Turns the local edits of `root` into a single commit on top of the commit they were
+// made on and points `branch` at it, creating the branch when it does not exist.
+pub fn publish(trees: &mut Trees, root: &TreeRoot, branch: &str, message: &str) -> Result<String, PublishError> {
+    let overlay = trees.overlay(root);
+    let baseCommit = match overlay.baseCommit() {
+        Some(baseCommit) if overlay.hasChanges() => baseCommit,
+        _ => return Err(PublishError::Failed(format!("nothing to publish for {}@{}", root.repository, root.reference))),
+    };
+    let branchHead = github::branchHead(&root.repository, branch).map_err(PublishError::Failed)?;
+    if let Some(ref branchHead) = branchHead {
+        if *branchHead != baseCommit {
+            return Err(PublishError::Conflict(format!(
+                "{} moved from {} to {} since the local edits were made",
+                branch, baseCommit, branchHead
+            )));
+        }
+    }
+    let (_, baseTree, _) = trees.rootCommit(root)
+        .map_err(|_| PublishError::Failed(format!("could not resolve {}", baseCommit)))?;
+    let mut entries = Vec::new();
+    collectChanges(trees, root, &overlay, "", &mut entries).map_err(PublishError::Failed)?;
+    let treeSha = github::createTree(&root.repository, &baseTree, &entries).map_err(PublishError::Failed)?;
+    let commitSha = github::createCommit(&root.repository, message, &treeSha, &baseCommit).map_err(PublishError::Failed)?;
+    let result = match branchHead {
+        Some(_) => github::updateBranch(&root.repository, branch, &commitSha),
+        None => github::createBranch(&root.repository, branch, &commitSha),
+    };
+    match result {
+        Ok(()) => (),
+        // the branch moved between the check above and the update
+        Err(ref error) if error.contains("HTTP 422") => {
+            return Err(PublishError::Conflict(format!("{} moved while publishing: {}", branch, error)));
+        },
+        Err(error) => return Err(PublishError::Failed(error)),
+    }
+    overlay.discard().map_err(PublishError::Failed)?;
+    trees.forgetRootCommit(root);
+    Ok(commitSha)
+}
+
+// A lookup that fails must fail the publish: skipping the entry would drop a deletion
+// from the commit, and the edits are discarded once it is pushed.
+fn remoteEntry(trees: &mut Trees, root: &TreeRoot, relativePath: &str) -> Result<Option<TreeEntry>, String> {
+    trees.remoteEntry(root, relativePath).map_err(|error| {
+        format!("could not look up {} in {}@{}: {}", relativePath, root.repository, root.reference, io::Error::from_raw_os_error(error))
+    })
+}
+
+fn collectChanges(
+    trees: &mut Trees,
+    root: &TreeRoot,
+    overlay: &Overlay,
+    relativePath: &str,
+    entries: &mut Vec<NewTreeEntry>,
+) -> Result<(), String> {
+    let upperPath = overlay.upperPath(relativePath);
+    let children = fs::read_dir(&upperPath)
+        .map_err(|error| format!("could not read {}: {}", upperPath.display(), error))?;
+    for child in children.filter_map(|child| child.ok()) {
+        let childName = child.file_name().to_string_lossy().to_string();
+        let childPath = match relativePath.len() {
+            0 => childName.clone(),
+            _ => format!("{}/{}", relativePath, childName),
+        };
+        if childName == OPAQUE_MARKER {
+            collectReplacedDirectory(trees, root, overlay, relativePath, entries)?;
+            continue;
+        }
+        if childName.starts_with(WHITEOUT_PREFIX) {
+            let deletedPath = match relativePath.len() {
+                0 => childName[WHITEOUT_PREFIX.len()..].to_string(),
+                _ => format!("{}/{}", relativePath, &childName[WHITEOUT_PREFIX.len()..]),
+            };
+            if let Some(entry) = remoteEntry(trees, root, &deletedPath)? {
+                entries.push(NewTreeEntry {
+                    path: deletedPath,
+                    mode: entry.mode,
+                    kind: entry.kind,
+                    sha: None,
+                });
+            }
+            continue;
+        }
+        let metadata = fs::symlink_metadata(child.path())
+            .map_err(|error| format!("could not read {}: {}", child.path().display(), error))?;
+        if metadata.is_dir() {
+            collectChanges(trees, root, overlay, &childPath, entries)?;
+            continue;
+        }
+        let (contents, mode) = if metadata.file_type().is_symlink() {
+            let target = fs::read_link(child.path()).map_err(|error| error.to_string())?;
+            (target.as_os_str().to_string_lossy().as_bytes().to_vec(), "120000")
+        } else {
+            let contents = fs::read(child.path()).map_err(|error| error.to_string())?;
+            let isExecutable = metadata.permissions().mode() & 0o111 != 0;
+            (contents, if isExecutable { "100755" } else { "100644" })
+        };
+        let blobSha = github::createBlob(&root.repository, &contents)?;
+        entries.push(NewTreeEntry {
+            path: childPath,
+            mode: mode.to_string(),
+            kind: "blob".to_string(),
+            sha: Some(blobSha),
+        });
+    }
+    Ok(())
+}
+
+// An opaque directory replaces the remote one: every remote file below it that was
+// not written again locally is deleted.
+fn collectReplacedDirectory(
+    trees: &mut Trees,
+    root: &TreeRoot,
+    overlay: &Overlay,
+    relativePath: &str,
+    entries: &mut Vec<NewTreeEntry>,
+) -> Result<(), String> {
+    let entry = match remoteEntry(trees, root, relativePath)? {
+        Some(ref entry) if entry.kind == "tree" => entry.clone(),
+        _ => return Ok(()),
+    };
+    for remoteEntry in github::listTreeRecursive(&root.repository, &entry.sha)? {
+        let path = format!("{}/{}", relativePath, remoteEntry.name);
+        if remoteEntry.kind == "tree" || overlay.upperPath(&path).exists() {
+            continue;
+        }
+        entries.push(NewTreeEntry {
+            path: path,
+            mode: remoteEntry.mode,
+            kind: remoteEntry.kind,
+            sha: None,
+        });
+    }
+    Ok(())
+}
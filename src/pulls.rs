@@ -0,0 +1,159 @@
+use fuser::FileType;
+use libc::{c_int, ENOENT};
+
+use issues::COMMENT_TEMPLATE;
+use tree::{TreeRoot, Trees};
+use views::{encodeName, pathChildren, ViewNode, ViewPath, Views};
+
+// `.pulls/<number>/`: the metadata, description, diff and comments of a pull request,
+// `files/` with the changed files at the head commit, and `base` / `head` symlinks to
+// the branches in `.refs`.
+
+const PULL_FILES: &[&str] = &["base", "comments.md", "description.md", "diff", "files", "head", "meta.json", "patch"];
+const DESCRIPTION_TEMPLATE: &str = r##""# \(.title)\n\n#\(.number) by @\(.user.login), \(.head.label) into \(.base.ref), \(if .merged_at then "merged" else .state end)\n\n\(.body // "")\n""##;
+const REVIEW_COMMENT_TEMPLATE: &str = r#".[] | "\n---\n\n**@\(.user.login)** on `\(.path)` line \(.line // .original_line) (\(.created_at)):\n\n\(.body // "")\n""#;
+
+struct PullRequest {
+    headSha: String,
+    // empty when the fork was deleted
+    headRepository: String,
+    headReference: String,
+    baseReference: String,
+}
+
+fn listPulls(views: &mut Views, fullRepositoryName: &str) -> Result<Vec<String>, c_int> {
+    let output = views.gh(&[
+        "api", "--paginate", &format!("repos/{}/pulls?state=all&per_page=100", fullRepositoryName), "--jq", ".[].number",
+    ])?;
+    Ok(output.lines().filter(|number| number.len() > 0).map(|number| number.to_string()).collect())
+}
+
+fn getPull(views: &mut Views, fullRepositoryName: &str, number: &str) -> Result<Option<PullRequest>, c_int> {
+    if number.parse::<u64>().is_err() {
+        return Ok(None);
+    }
+    let output = match views.gh(&[
+        "api", &format!("repos/{}/pulls/{}", fullRepositoryName, number),
+        "--jq", r#"[.head.sha, .head.repo.full_name // "", .head.ref, .base.ref] | @tsv"#,
+    ]) {
+        Ok(output) => output,
+        Err(ENOENT) => return Ok(None),
+        Err(error) => return Err(error),
+    };
+    let fields: Vec<&str> = output.split("\t").collect();
+    match fields.as_slice() {
+        [headSha, headRepository, headReference, baseReference] => Ok(Some(PullRequest {
+            headSha: headSha.to_string(),
+            headRepository: headRepository.to_string(),
+            headReference: headReference.to_string(),
+            baseReference: baseReference.to_string(),
+        })),
+        _ => Ok(None),
+    }
+}
+
+// Paths of the files the pull request adds or modifies.
+fn changedFiles(views: &mut Views, fullRepositoryName: &str, number: &str) -> Result<Vec<String>, c_int> {
+    let output = views.gh(&[
+        "api", "--paginate", &format!("repos/{}/pulls/{}/files?per_page=100", fullRepositoryName, number),
+        "--jq", r#".[] | select(.status != "removed") | .filename"#,
+    ])?;
+    Ok(output.lines().filter(|path| path.len() > 0).map(|path| path.to_string()).collect())
+}
+
+fn renderComments(views: &mut Views, fullRepositoryName: &str, number: &str) -> Result<Vec<u8>, c_int> {
+    let conversation = views.gh(&[
+        "api", "--paginate", &format!("repos/{}/issues/{}/comments?per_page=100", fullRepositoryName, number),
+        "--jq", COMMENT_TEMPLATE,
+    ])?;
+    let reviewComments = views.gh(&[
+        "api", "--paginate", &format!("repos/{}/pulls/{}/comments?per_page=100", fullRepositoryName, number),
+        "--jq", REVIEW_COMMENT_TEMPLATE,
+    ])?;
+    Ok(format!("# Conversation\n{}\n\n# Review comments\n{}\n", conversation, reviewComments).into_bytes())
+}
+
+// Relative target of the `base` and `head` symlinks, from `owner/repo/.pulls/<number>/`.
+fn branchLink(fullRepositoryName: &str, branchRepository: &str, branch: &str) -> String {
+    match branchRepository == fullRepositoryName || branchRepository.len() == 0 {
+        true => format!("../../.refs/branches/{}", encodeName(branch)),
+        false => format!("../../../../{}/.refs/branches/{}", branchRepository, encodeName(branch)),
+    }
+}
+
+pub fn resolve(views: &mut Views, trees: &mut Trees, path: &ViewPath) -> Result<Option<ViewNode>, c_int> {
+    let number = match path.rest.first() {
+        Some(number) => number.clone(),
+        None => return Ok(Some(ViewNode::Directory)),
+    };
+    let pull = match getPull(views, &path.repository, &number)? {
+        Some(pull) => pull,
+        None => return Ok(None),
+    };
+    let pullPath = format!("repos/{}/pulls/{}", path.repository, number);
+    let name = match path.rest.get(1) {
+        Some(name) => name.as_str(),
+        None => return Ok(Some(ViewNode::Directory)),
+    };
+    let node = match (name, path.rest.len()) {
+        ("meta.json", 2) => ViewNode::File(views.ghFile(&["api", &pullPath])?),
+        ("description.md", 2) => ViewNode::File(views.ghFile(&["api", &pullPath, "--jq", DESCRIPTION_TEMPLATE])?),
+        ("diff", 2) => ViewNode::File(views.ghFile(&["api", &pullPath, "-H", "Accept: application/vnd.github.diff"])?),
+        ("patch", 2) => ViewNode::File(views.ghFile(&["api", &pullPath, "-H", "Accept: application/vnd.github.patch"])?),
+        ("comments.md", 2) => ViewNode::File(renderComments(views, &path.repository, &number)?),
+        ("base", 2) => ViewNode::Symlink(branchLink(&path.repository, &path.repository, &pull.baseReference)),
+        ("head", 2) => ViewNode::Symlink(branchLink(&path.repository, &pull.headRepository, &pull.headReference)),
+        ("files", _) => {
+            let relativePath = path.rest[2..].join("/");
+            if relativePath.len() == 0 {
+                return Ok(Some(ViewNode::Directory));
+            }
+            let changedFiles = changedFiles(views, &path.repository, &number)?;
+            if !changedFiles.contains(&relativePath) {
+                let isDirectory = changedFiles.iter().any(|changedFile| changedFile.starts_with(&(relativePath.clone() + "/")));
+                return Ok(if isDirectory { Some(ViewNode::Directory) } else { None });
+            }
+            let headRoot = TreeRoot {
+                repository: if pull.headRepository.len() > 0 { pull.headRepository } else { path.repository.clone() },
+                reference: pull.headSha,
+            };
+            match trees.remoteEntry(&headRoot, &relativePath)? {
+                Some(entry) => ViewNode::Blob {
+                    repository: headRoot.repository,
+                    sha: entry.sha,
+                    size: entry.size,
+                },
+                None => return Ok(None),
+            }
+        },
+        _ => return Ok(None),
+    };
+    Ok(Some(node))
+}
+
+pub fn list(views: &mut Views, path: &ViewPath) -> Result<Vec<(String, FileType)>, c_int> {
+    let number = match path.rest.first() {
+        Some(number) => number.clone(),
+        None => {
+            return Ok(listPulls(views, &path.repository)?
+                .into_iter()
+                .map(|number| (number, FileType::Directory))
+                .collect());
+        },
+    };
+    match path.rest.get(1).map(|name| name.as_str()) {
+        None => Ok(PULL_FILES
+            .iter()
+            .map(|name| {
+                let kind = match *name {
+                    "files" => FileType::Directory,
+                    "base" | "head" => FileType::Symlink,
+                    _ => FileType::RegularFile,
+                };
+                (name.to_string(), kind)
+            })
+            .collect()),
+        Some("files") => Ok(pathChildren(&changedFiles(views, &path.repository, &number)?, &path.rest[2..].join("/"))),
+        Some(_) => Err(ENOENT),
+    }
+}
@@ -0,0 +1,158 @@
+use fuser::FileType;
+use libc::{c_int, EBUSY, EEXIST, ENOENT, ENOTSUP, EPERM};
+
+use github;
+use tree::{TreeRoot, Trees};
+use views::{self, decodeName, encodeName, ViewNode, ViewPath, Views};
+
+// `.refs/branches/<name>` and `.refs/tags/<name>`: every ref is a directory showing
+// its tree, `mkdir` creates a ref and `rmdir` deletes it.
+
+pub const SOURCE_ATTRIBUTE: &str = "user.github.source";
+
+// directory name -> namespace below `refs/`
+fn refNamespace(kind: &str) -> Option<&'static str> {
+    match kind {
+        "branches" => Some("heads"),
+        "tags" => Some("tags"),
+        _ => None,
+    }
+}
+
+// Tags are read-only: publishing one would make a branch of the same name, and its
+// edits would share the overlay of that branch.
+pub fn isTagRoot(rootPath: &str) -> bool {
+    match views::parseViewPath(rootPath) {
+        Some(path) => path.view == ".refs" && path.rest.len() == 2 && path.rest[0] == "tags",
+        None => false,
+    }
+}
+
+fn listRefs(views: &mut Views, fullRepositoryName: &str, kind: &str) -> Result<Vec<String>, c_int> {
+    let output = views.gh(&[
+        "api", "--paginate", &format!("repos/{}/{}", fullRepositoryName, kind), "--jq", ".[].name",
+    ])?;
+    Ok(output.lines().filter(|name| name.len() > 0).map(|name| name.to_string()).collect())
+}
+
+pub fn resolve(views: &mut Views, trees: &mut Trees, path: &ViewPath) -> Result<Option<ViewNode>, c_int> {
+    match path.rest.as_slice() {
+        [] => Ok(Some(ViewNode::Directory)),
+        [ref kind] => Ok(refNamespace(kind).map(|_| ViewNode::Directory)),
+        [ref kind, ref name] => {
+            if refNamespace(kind).is_none() {
+                return Ok(None);
+            }
+            let reference = decodeName(name);
+            let isKnown = trees.isRegistered(&path.fullPath);
+            if !isKnown && !listRefs(views, &path.repository, kind)?.contains(&reference) {
+                return Ok(None);
+            }
+            // everything below the ref is served by its tree
+            trees.registerRoot(&path.fullPath, TreeRoot {
+                repository: path.repository.clone(),
+                reference: reference,
+            });
+            Ok(Some(ViewNode::Directory))
+        },
+        _ => Ok(None),
+    }
+}
+
+pub fn list(views: &mut Views, path: &ViewPath) -> Result<Vec<(String, FileType)>, c_int> {
+    match path.rest.as_slice() {
+        [] => Ok(vec![
+            ("branches".to_string(), FileType::Directory),
+            ("tags".to_string(), FileType::Directory),
+        ]),
+        [ref kind] if refNamespace(kind).is_some() => Ok(listRefs(views, &path.repository, kind)?
+            .iter()
+            .map(|name| (encodeName(name), FileType::Directory))
+            .collect()),
+        _ => Err(ENOENT),
+    }
+}
+
+// `mkdir .refs/branches/name` starts the branch at the head of the default branch,
+// `mkdir .refs/branches/name@source` or a `user.github.source` attribute on the
+// `branches` directory start it somewhere else. Same for tags.
+pub fn mkdir(views: &mut Views, trees: &mut Trees, path: &ViewPath) -> Result<String, c_int> {
+    let (kind, requestedName) = match path.rest.as_slice() {
+        [ref kind, ref name] => (kind, name),
+        _ => return Err(EPERM),
+    };
+    let namespace = refNamespace(kind).ok_or(EPERM)?;
+    let parentPath = format!("{}/.refs/{}", path.repository, kind);
+    let (name, source) = match requestedName.find("@") {
+        Some(index) => (decodeName(&requestedName[..index]), Some(requestedName[index + 1..].to_string())),
+        None => (decodeName(requestedName), views.refSources.get(&parentPath).cloned()),
+    };
+    let source = match source {
+        Some(source) => source,
+        None => github::defaultBranch(&path.repository).map_err(|error| views::errorCode(&error))?,
+    };
+    let (commitSha, _, _) = github::resolveCommit(&path.repository, &source).map_err(|error| views::errorCode(&error))?;
+    github::createReference(&path.repository, &format!("refs/{}/{}", namespace, name), &commitSha)
+        .map_err(|error| match error.contains("HTTP 422") {
+            true => EEXIST,
+            false => views::errorCode(&error),
+        })?;
+    views.invalidate(&path.repository);
+    let createdPath = format!("{}/{}", parentPath, encodeName(&name));
+    trees.registerRoot(&createdPath, TreeRoot {
+        repository: path.repository.clone(),
+        reference: name,
+    });
+    Ok(createdPath)
+}
+
+// Protected branches and the default branch are never deleted, nor refs with local
+// edits that were not published yet.
+pub fn rmdir(views: &mut Views, trees: &mut Trees, path: &ViewPath) -> Result<(), c_int> {
+    let (kind, name) = match path.rest.as_slice() {
+        [ref kind, ref name] => (kind, decodeName(name)),
+        _ => return Err(EPERM),
+    };
+    let namespace = refNamespace(kind).ok_or(EPERM)?;
+    let root = TreeRoot {
+        repository: path.repository.clone(),
+        reference: name.clone(),
+    };
+    if trees.overlay(&root).hasChanges() {
+        return Err(EBUSY);
+    }
+    if namespace == "heads" {
+        if github::defaultBranch(&path.repository).map_err(|error| views::errorCode(&error))? == name {
+            return Err(EBUSY);
+        }
+        let isProtected = views.gh(&[
+            "api", &format!("repos/{}/branches/{}", path.repository, name), "--jq", ".protected",
+        ])?;
+        if isProtected == "true" {
+            return Err(EPERM);
+        }
+    }
+    github::deleteReference(&path.repository, &format!("{}/{}", namespace, name))
+        .map_err(|error| match error.contains("HTTP 422") {
+            true => EPERM,
+            false => views::errorCode(&error),
+        })?;
+    views.invalidate(&path.repository);
+    trees.unregisterRoot(&path.fullPath);
+    trees.forgetRootCommit(&root);
+    Ok(())
+}
+
+pub fn setAttribute(views: &mut Views, path: &ViewPath, name: &str, value: &[u8]) -> Result<(), c_int> {
+    match path.rest.as_slice() {
+        [ref kind] if refNamespace(kind).is_some() && name == SOURCE_ATTRIBUTE => {
+            let source = String::from_utf8_lossy(value).trim().to_string();
+            match source.len() {
+                0 => views.refSources.remove(&path.fullPath),
+                _ => views.refSources.insert(path.fullPath.clone(), source),
+            };
+            Ok(())
+        },
+        _ => Err(ENOTSUP),
+    }
+}
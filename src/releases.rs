@@ -0,0 +1,163 @@
+use fuser::FileType;
+use libc::{c_int, EEXIST, ENOENT, EPERM};
+use std::path::Path;
+
+use github;
+use views::{self, decodeName, encodeName, ViewNode, ViewPath, Views};
+
+// `.releases/<tag>/`: `notes.md`, `meta.json` and the release assets. Assets report
+// their size from the API and are downloaded to the downloads cache the first time they
+// are read.
+//
+// A file created in a release directory is uploaded as an asset when it is closed,
+// `mkdir .releases/<tag>` creates a draft release.
+
+const RELEASE_FILES: &[&str] = &["meta.json", "notes.md"];
+
+const NOTES_TEMPLATE: &str = r##""# \(.name // .tag_name)\n\n\(.tag_name)\(if .draft then ", draft" else "" end)\(if .prerelease then ", pre-release" else "" end), published \(.published_at // "never") by @\(.author.login)\n\n\(.body // "")""##;
+
+pub struct Asset {
+    pub name: String,
+    pub id: String,
+    pub size: u64,
+}
+
+// tag -> release id, drafts included (they have no tag yet for `releases/tags/<tag>`).
+fn listReleases(views: &mut Views, fullRepositoryName: &str) -> Result<Vec<(String, String)>, c_int> {
+    let output = views.gh(&[
+        "api", "--paginate", &format!("repos/{}/releases?per_page=100", fullRepositoryName),
+        "--jq", ".[] | [.tag_name, .id] | @tsv",
+    ])?;
+    Ok(output
+        .lines()
+        .filter_map(|line| {
+            let fields: Vec<&str> = line.split("\t").collect();
+            match fields.as_slice() {
+                [tag, id] => Some((tag.to_string(), id.to_string())),
+                _ => None,
+            }
+        })
+        .collect())
+}
+
+pub fn releaseId(views: &mut Views, fullRepositoryName: &str, directoryName: &str) -> Result<Option<String>, c_int> {
+    let tag = decodeName(directoryName);
+    Ok(listReleases(views, fullRepositoryName)?
+        .into_iter()
+        .find(|&(ref releaseTag, _)| *releaseTag == tag)
+        .map(|(_, id)| id))
+}
+
+pub fn listAssets(views: &mut Views, fullRepositoryName: &str, releaseId: &str) -> Result<Vec<Asset>, c_int> {
+    let output = views.gh(&[
+        "api", &format!("repos/{}/releases/{}", fullRepositoryName, releaseId),
+        "--jq", ".assets[] | [.name, .id, .size] | @tsv",
+    ])?;
+    let mut assets = Vec::new();
+    for line in output.lines() {
+        let fields: Vec<&str> = line.split("\t").collect();
+        if fields.len() != 3 {
+            continue;
+        }
+        assets.push(Asset {
+            name: fields[0].to_string(),
+            id: fields[1].to_string(),
+            size: fields[2].parse::<u64>().unwrap_or(0),
+        });
+    }
+    Ok(assets)
+}
+
+pub fn resolve(views: &mut Views, path: &ViewPath) -> Result<Option<ViewNode>, c_int> {
+    let releaseId = match path.rest.first() {
+        Some(directoryName) => match releaseId(views, &path.repository, directoryName)? {
+            Some(releaseId) => releaseId,
+            None => return Ok(None),
+        },
+        None => return Ok(Some(ViewNode::Directory)),
+    };
+    let releasePath = format!("repos/{}/releases/{}", path.repository, releaseId);
+    let node = match path.rest.get(1).map(|name| name.as_str()) {
+        None => ViewNode::Directory,
+        Some(_) if path.rest.len() > 2 => return Ok(None),
+        Some(_) if views.pendingInputs.contains_key(&path.fullPath) => views.inputNode(&path.fullPath).unwrap(),
+        Some("meta.json") => ViewNode::File(views.ghFile(&["api", &releasePath])?),
+        Some("notes.md") => ViewNode::File(views.ghFile(&["api", &releasePath, "--jq", NOTES_TEMPLATE])?),
+        Some(name) => match listAssets(views, &path.repository, &releaseId)?.into_iter().find(|asset| asset.name == name) {
+            Some(asset) => ViewNode::Download {
+                endpoint: format!("repos/{}/releases/assets/{}", path.repository, asset.id),
+                fileName: format!("asset-{}", asset.id),
+                size: asset.size,
+            },
+            None => return Ok(None),
+        },
+    };
+    Ok(Some(node))
+}
+
+pub fn list(views: &mut Views, path: &ViewPath) -> Result<Vec<(String, FileType)>, c_int> {
+    match path.rest.as_slice() {
+        [] => Ok(listReleases(views, &path.repository)?
+            .into_iter()
+            .map(|(tag, _)| (encodeName(&tag), FileType::Directory))
+            .collect()),
+        [ref directoryName] => {
+            let releaseId = releaseId(views, &path.repository, directoryName)?.ok_or(ENOENT)?;
+            let mut children: Vec<(String, FileType)> = RELEASE_FILES
+                .iter()
+                .map(|name| (name.to_string(), FileType::RegularFile))
+                .collect();
+            for asset in listAssets(views, &path.repository, &releaseId)? {
+                children.push((asset.name, FileType::RegularFile));
+            }
+            let prefix = path.fullPath.clone() + "/";
+            for pendingPath in views.pendingInputs.keys() {
+                if pendingPath.starts_with(&prefix) {
+                    children.push((pendingPath[prefix.len()..].to_string(), FileType::RegularFile));
+                }
+            }
+            Ok(children)
+        },
+        _ => Err(ENOENT),
+    }
+}
+
+// Existing assets are not replaced: GitHub refuses two assets with the same name.
+pub fn create(views: &mut Views, path: &ViewPath) -> Result<(), c_int> {
+    let (directoryName, name) = match path.rest.as_slice() {
+        [ref directoryName, ref name] => (directoryName, name),
+        _ => return Err(EPERM),
+    };
+    let releaseId = releaseId(views, &path.repository, directoryName)?.ok_or(ENOENT)?;
+    let isTaken = RELEASE_FILES.contains(&name.as_str())
+        || views.pendingInputs.contains_key(&path.fullPath)
+        || listAssets(views, &path.repository, &releaseId)?.iter().any(|asset| asset.name == *name);
+    if isTaken {
+        return Err(EEXIST);
+    }
+    views.startInput(&path.fullPath).map(|_| ())
+}
+
+pub fn submit(views: &mut Views, path: &ViewPath, inputPath: &Path) -> Result<(), c_int> {
+    let (directoryName, name) = match path.rest.as_slice() {
+        [ref directoryName, ref name] => (directoryName, name),
+        _ => return Err(EPERM),
+    };
+    let releaseId = releaseId(views, &path.repository, directoryName)?.ok_or(ENOENT)?;
+    let result = github::uploadAsset(&path.repository, &releaseId, name, inputPath);
+    views.invalidate(&path.repository);
+    result.map_err(|error| views::errorCode(&error))
+}
+
+pub fn mkdir(views: &mut Views, path: &ViewPath) -> Result<String, c_int> {
+    let directoryName = match path.rest.as_slice() {
+        [ref directoryName] => directoryName,
+        _ => return Err(EPERM),
+    };
+    if releaseId(views, &path.repository, directoryName)?.is_some() {
+        return Err(EEXIST);
+    }
+    let result = github::createDraftRelease(&path.repository, &decodeName(directoryName));
+    views.invalidate(&path.repository);
+    result.map(|_| path.fullPath.clone()).map_err(|error| views::errorCode(&error))
+}
@@ -0,0 +1,73 @@
+use fuser::FileType;
+use libc::{c_int, ENOENT};
+use time;
+
+use tree::{TreeRoot, Trees};
+use views::{ViewNode, ViewPath, Views};
+
+// `.at/<date>/` (or `owner/repo@{<date>}/`): the tree of the last commit of the default
+// branch before `<date>`, a day (`2025-01-01`, midnight UTC) or a time
+// (`2025-01-01T12:00:00Z`). Dates can't be listed, only entered by name.
+
+// The API timestamp `name` stands for.
+fn parseDate(name: &str) -> Option<String> {
+    if name.len() == 10 && time::strptime(name, "%Y-%m-%d").is_ok() {
+        return Some(format!("{}T00:00:00Z", name));
+    }
+    match time::strptime(name, "%Y-%m-%dT%H:%M:%SZ") {
+        Ok(_) => Some(name.to_string()),
+        Err(_) => None,
+    }
+}
+
+// None when the repository has no commit that old.
+fn commitBefore(views: &mut Views, fullRepositoryName: &str, date: &str) -> Result<Option<String>, c_int> {
+    let sha = views.gh(&[
+        "api", &format!("repos/{}/commits?until={}&per_page=1", fullRepositoryName, date), "--jq", ".[0].sha // empty",
+    ])?;
+    Ok(if sha.len() > 0 { Some(sha) } else { None })
+}
+
+pub fn resolve(views: &mut Views, trees: &mut Trees, path: &ViewPath) -> Result<Option<ViewNode>, c_int> {
+    match path.rest.as_slice() {
+        [] => Ok(Some(ViewNode::Directory)),
+        [ref name] => {
+            let date = match parseDate(name) {
+                Some(date) => date,
+                None => return Ok(None),
+            };
+            let commitSha = match commitBefore(views, &path.repository, &date)? {
+                Some(commitSha) => commitSha,
+                None => return Ok(None),
+            };
+            // everything below the date is served by the tree of the commit
+            trees.registerRoot(&path.fullPath, TreeRoot {
+                repository: path.repository.clone(),
+                reference: commitSha,
+            });
+            Ok(Some(ViewNode::Directory))
+        },
+        _ => Ok(None),
+    }
+}
+
+pub fn list(_views: &mut Views, path: &ViewPath) -> Result<Vec<(String, FileType)>, c_int> {
+    match path.rest.len() {
+        0 => Ok(Vec::new()),
+        _ => Err(ENOENT),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parsesDates() {
+        assert_eq!(parseDate("2025-01-01"), Some("2025-01-01T00:00:00Z".to_string()));
+        assert_eq!(parseDate("2025-01-01T12:30:00Z"), Some("2025-01-01T12:30:00Z".to_string()));
+        assert_eq!(parseDate("2025-1-1"), None);
+        assert_eq!(parseDate("2025-01-01 12:30"), None);
+        assert_eq!(parseDate("yesterday"), None);
+    }
+}
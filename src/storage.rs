@@ -0,0 +1,53 @@
+use std::fs;
+use std::path::PathBuf;
+
+use config::{xdgDirectory, MountConfig};
+
+// Where everything gh-mount keeps on disk lives. With `storage_root` set all the
+// directories share that root, otherwise clones, overlays and metadata go to
+// `$XDG_DATA_HOME` (they can hold local work) and blobs and downloads (release
+// assets, workflow artifacts and logs) to `$XDG_CACHE_HOME` (safe to delete).
+pub struct Storage {
+    pub clonesDirectory: PathBuf,
+    pub blobsDirectory: PathBuf,
+    pub downloadsDirectory: PathBuf,
+    pub metadataDirectory: PathBuf,
+    pub overlaysDirectory: PathBuf,
+}
+
+impl Storage {
+    pub fn resolve(config: &MountConfig) -> Result<Storage, String> {
+        let (dataRoot, cacheRoot) = match config.storageRoot {
+            Some(ref root) => (root.clone(), root.clone()),
+            None => {
+                let dataHome = xdgDirectory("XDG_DATA_HOME", ".local/share");
+                let cacheHome = xdgDirectory("XDG_CACHE_HOME", ".cache");
+                match (dataHome, cacheHome) {
+                    (Some(dataHome), Some(cacheHome)) => (dataHome.join("gh_mount"), cacheHome.join("gh_mount")),
+                    _ => return Err("HOME is not set, configure storage_root".to_string()),
+                }
+            }
+        };
+        let storage = Storage {
+            clonesDirectory: dataRoot.join("clones"),
+            blobsDirectory: cacheRoot.join("blobs"),
+            downloadsDirectory: cacheRoot.join("downloads"),
+            metadataDirectory: dataRoot.join("metadata"),
+            overlaysDirectory: dataRoot.join("overlays"),
+        };
+        for directory in [
+            &storage.clonesDirectory,
+            &storage.blobsDirectory,
+            &storage.downloadsDirectory,
+            &storage.metadataDirectory,
+            &storage.overlaysDirectory,
+        ].iter() {
+            fs::create_dir_all(directory)
+                .map_err(|error| format!("could not create {}: {}", directory.display(), error))?;
+        }
+        Ok(storage)
+    }
+    pub fn clonePath(&self, fullRepositoryName: &str) -> PathBuf {
+        self.clonesDirectory.join(fullRepositoryName)
+    }
+}
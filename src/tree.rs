@@ -0,0 +1,495 @@
+use fuser::{FileAttr, FileType};
+use libc::{c_int, EEXIST, EIO, EISDIR, ENOENT, ENOTDIR, ENOTEMPTY, EPERM, EXDEV};
+use std::collections::HashMap;
+use std::fs::{self, OpenOptions};
+use std::os::unix::fs::{OpenOptionsExt, PermissionsExt};
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use attributes::{self, Owner};
+use github::{self, TreeEntry};
+use overlay::{Overlay, WHITEOUT_PREFIX};
+use passthrough;
+use views;
+
+// A directory of the mount that shows the tree of `repository` at `reference`.
+#[derive(Clone)]
+pub struct TreeRoot {
+    pub repository: String,
+    pub reference: String,
+}
+
+impl TreeRoot {
+    // Roots at a commit (`.at/<date>/`, `.commits/<date>/<sha>/tree/`...) are
+    // snapshots, only branches can be edited.
+    pub fn isCommit(&self) -> bool {
+        self.reference.len() == 40 && self.reference.chars().all(|character| character.is_digit(16))
+    }
+}
+
+pub struct TreeLocation {
+    pub root: TreeRoot,
+    // mount path of the directory showing the root
+    pub rootPath: String,
+    // path below the tree root, "" for the root itself
+    pub relativePath: String,
+}
+
+pub enum TreeNode {
+    Upper(PathBuf),
+    Remote(TreeEntry),
+    Root,
+}
+
+// The remote view of repositories that were not cloned: trees and blobs are fetched
+// lazily from the API, and local edits are kept in a copy-on-write overlay per
+// repository and ref.
+pub struct Trees {
+    blobsDirectory: PathBuf,
+    overlaysDirectory: PathBuf,
+    roots: HashMap<String, TreeRoot>,
+    // `repository@reference` -> (commit sha, tree sha, commit time), with the time it
+    // was resolved at
+    rootCommits: HashMap<String, ((String, String, SystemTime), SystemTime)>,
+    // with `file_times = last_commit`, `repository@commit sha:path` -> time of the last
+    // commit touching the path
+    lastCommitTimes: Option<HashMap<String, SystemTime>>,
+    // `repository:tree sha` -> entries, trees never change once fetched
+    treeEntries: HashMap<String, Vec<TreeEntry>>,
+    owner: Owner,
+}
+
+impl Trees {
+    pub fn new(blobsDirectory: &Path, overlaysDirectory: &Path, lastCommitTimes: bool, owner: Owner) -> Trees {
+        Trees {
+            blobsDirectory: blobsDirectory.to_path_buf(),
+            overlaysDirectory: overlaysDirectory.to_path_buf(),
+            roots: HashMap::new(),
+            rootCommits: HashMap::new(),
+            lastCommitTimes: if lastCommitTimes { Some(HashMap::new()) } else { None },
+            treeEntries: HashMap::new(),
+            owner: owner,
+        }
+    }
+    pub fn registerRoot(&mut self, fullPath: &str, root: TreeRoot) {
+        self.roots.insert(fullPath.to_string(), root);
+    }
+    pub fn isRegistered(&self, fullPath: &str) -> bool {
+        self.roots.contains_key(fullPath)
+    }
+    pub fn unregisterRoot(&mut self, fullPath: &str) {
+        self.roots.remove(fullPath);
+    }
+    // Finds the tree `fullPath` belongs to. `user/repo` is the tree of the default
+    // branch, other roots are registered by the directories that expose them.
+    pub fn locate(&mut self, fullPath: &str) -> Option<TreeLocation> {
+        let pathParts: Vec<&str> = fullPath.split("/").collect();
+        for rootLength in (1..pathParts.len() + 1).rev() {
+            let rootPath = pathParts[..rootLength].join("/");
+            if let Some(root) = self.roots.get(&rootPath) {
+                return Some(TreeLocation {
+                    root: root.clone(),
+                    rootPath: rootPath,
+                    relativePath: pathParts[rootLength..].join("/"),
+                });
+            }
+        }
+        if pathParts.len() < 2 {
+            return None;
+        }
+        let fullRepositoryName = pathParts[..2].join("/");
+        let defaultBranch = match github::defaultBranch(&fullRepositoryName) {
+            Ok(defaultBranch) => defaultBranch,
+            Err(error) => {
+                eprintln!("gh-mount: {}", error);
+                return None;
+            }
+        };
+        self.registerRoot(&fullRepositoryName, TreeRoot {
+            repository: fullRepositoryName.clone(),
+            reference: defaultBranch,
+        });
+        self.locate(fullPath)
+    }
+    pub fn overlay(&self, root: &TreeRoot) -> Overlay {
+        Overlay::new(&self.overlaysDirectory, &root.repository, &root.reference)
+    }
+    // The commit a tree root shows. With pending local edits this stays the commit the
+    // edits were made on, so they keep applying to the tree they were made against;
+    // otherwise the ref is resolved again once the cached commit is older than
+    // CACHE_TTL, so the tree follows its branch.
+    pub fn rootCommit(&mut self, root: &TreeRoot) -> Result<(String, String, SystemTime), c_int> {
+        let key = format!("{}@{}", root.repository, root.reference);
+        let baseCommit = self.overlay(root).baseCommit();
+        let isPinned = baseCommit.is_some() || root.isCommit();
+        if let Some(&(ref rootCommit, resolvedAt)) = self.rootCommits.get(&key) {
+            if isPinned || views::isFresh(resolvedAt) {
+                return Ok(rootCommit.clone());
+            }
+        }
+        let reference = baseCommit.unwrap_or(root.reference.clone());
+        let rootCommit = github::resolveCommit(&root.repository, &reference).map_err(|error| {
+            eprintln!("gh-mount: {}", error);
+            EIO
+        })?;
+        self.rootCommits.insert(key, (rootCommit.clone(), SystemTime::now()));
+        Ok(rootCommit)
+    }
+    // Forgets the resolved commit, the next access shows the current head of the ref.
+    pub fn forgetRootCommit(&mut self, root: &TreeRoot) {
+        self.rootCommits.remove(&format!("{}@{}", root.repository, root.reference));
+    }
+    pub fn listRemoteTree(&mut self, fullRepositoryName: &str, treeSha: &str) -> Result<Vec<TreeEntry>, c_int> {
+        let key = format!("{}:{}", fullRepositoryName, treeSha);
+        if let Some(entries) = self.treeEntries.get(&key) {
+            return Ok(entries.clone());
+        }
+        let entries = github::listTree(fullRepositoryName, treeSha).map_err(|error| {
+            eprintln!("gh-mount: {}", error);
+            EIO
+        })?;
+        self.treeEntries.insert(key, entries.clone());
+        Ok(entries)
+    }
+    // The entry at `relativePath` in the remote tree, ignoring local edits.
+    pub fn remoteEntry(&mut self, root: &TreeRoot, relativePath: &str) -> Result<Option<TreeEntry>, c_int> {
+        let (_, mut treeSha, _) = self.rootCommit(root)?;
+        let pathParts: Vec<&str> = relativePath.split("/").filter(|pathPart| pathPart.len() > 0).collect();
+        for (index, pathPart) in pathParts.iter().enumerate() {
+            let entries = self.listRemoteTree(&root.repository, &treeSha)?;
+            let entry = match entries.into_iter().find(|entry| entry.name == *pathPart) {
+                Some(entry) => entry,
+                None => return Ok(None),
+            };
+            if index == pathParts.len() - 1 {
+                return Ok(Some(entry));
+            }
+            if entry.kind != "tree" {
+                return Ok(None);
+            }
+            treeSha = entry.sha;
+        }
+        Ok(None)
+    }
+    pub fn resolve(&mut self, location: &TreeLocation) -> Result<Option<TreeNode>, c_int> {
+        if location.relativePath.len() == 0 {
+            return Ok(Some(TreeNode::Root));
+        }
+        let overlay = self.overlay(&location.root);
+        let upperPath = overlay.upperPath(&location.relativePath);
+        if fs::symlink_metadata(&upperPath).is_ok() {
+            return Ok(Some(TreeNode::Upper(upperPath)));
+        }
+        if overlay.isHidden(&location.relativePath) {
+            return Ok(None);
+        }
+        Ok(self.remoteEntry(&location.root, &location.relativePath)?.map(TreeNode::Remote))
+    }
+    // Remote files show the time of the commit the tree is shown at, or of the last
+    // commit touching them with `file_times = last_commit`.
+    fn commitTime(&mut self, location: &TreeLocation) -> SystemTime {
+        let (commitSha, _, commitTime) = match self.rootCommit(&location.root) {
+            Ok(rootCommit) => rootCommit,
+            Err(_) => return SystemTime::now(),
+        };
+        let lastCommitTimes = match self.lastCommitTimes {
+            Some(ref mut lastCommitTimes) if location.relativePath.len() > 0 => lastCommitTimes,
+            _ => return commitTime,
+        };
+        let key = format!("{}@{}:{}", location.root.repository, commitSha, location.relativePath);
+        if let Some(lastCommitTime) = lastCommitTimes.get(&key) {
+            return *lastCommitTime;
+        }
+        let lastCommitTime = match github::lastCommitTime(&location.root.repository, &commitSha, &location.relativePath) {
+            Ok(lastCommitTime) => lastCommitTime.unwrap_or(commitTime),
+            Err(error) => {
+                eprintln!("gh-mount: {}", error);
+                return commitTime;
+            }
+        };
+        lastCommitTimes.insert(key, lastCommitTime);
+        lastCommitTime
+    }
+    pub fn attr(&mut self, ino: u64, location: &TreeLocation, node: &TreeNode) -> Option<FileAttr> {
+        if let TreeNode::Upper(ref upperPath) = *node {
+            let metadata = fs::symlink_metadata(upperPath).ok()?;
+            return Some(passthrough::attrFromMetadata(ino, &metadata, self.owner));
+        }
+        let ts = self.commitTime(location);
+        let (kind, perm, size) = match *node {
+            TreeNode::Upper(_) => return None,
+            TreeNode::Root => (FileType::Directory, 0o755, 0),
+            TreeNode::Remote(ref entry) => match entry.mode.as_str() {
+                "040000" | "160000" => (FileType::Directory, 0o755, 0),
+                "120000" => (FileType::Symlink, 0o777, entry.size),
+                "100755" => (FileType::RegularFile, 0o755, entry.size),
+                _ => (FileType::RegularFile, 0o644, entry.size),
+            },
+        };
+        let attr = attributes::nodeAttr(ino, kind, perm, size, ts, self.owner);
+        // The subdirectories of a remote directory are only counted when its listing
+        // was already fetched, a stat should not cost an API call.
+        let subdirectories = match *node {
+            TreeNode::Remote(ref entry) if entry.kind == "tree" => self
+                .treeEntries
+                .get(&format!("{}:{}", location.root.repository, entry.sha))
+                .map(|entries| entries.iter().filter(|child| child.kind == "tree" || child.kind == "commit").count()),
+            _ => None,
+        };
+        Some(match subdirectories {
+            Some(subdirectories) => FileAttr { nlink: 2 + subdirectories as u32, ..attr },
+            None => attr,
+        })
+    }
+    // Remote children merged with the upper directory, minus local deletions.
+    pub fn list(&mut self, location: &TreeLocation) -> Result<Vec<(String, FileType)>, c_int> {
+        let overlay = self.overlay(&location.root);
+        let mut children: Vec<(String, FileType)> = Vec::new();
+        let hasRemoteChildren = !overlay.isOpaque(&location.relativePath) && !overlay.isHidden(&location.relativePath);
+        if hasRemoteChildren {
+            let treeSha = match self.resolve(location)? {
+                Some(TreeNode::Root) => self.rootCommit(&location.root)?.1,
+                Some(TreeNode::Remote(ref entry)) if entry.kind == "tree" => entry.sha.clone(),
+                Some(TreeNode::Remote(_)) => return Err(ENOTDIR),
+                Some(TreeNode::Upper(_)) => match self.remoteEntry(&location.root, &location.relativePath)? {
+                    Some(ref entry) if entry.kind == "tree" => entry.sha.clone(),
+                    _ => String::new(),
+                },
+                None => return Err(ENOENT),
+            };
+            if treeSha.len() > 0 {
+                let whiteouts = overlay.whiteouts(&location.relativePath);
+                for entry in self.listRemoteTree(&location.root.repository, &treeSha)? {
+                    if whiteouts.contains(&entry.name) {
+                        continue;
+                    }
+                    let kind = match entry.mode.as_str() {
+                        "040000" | "160000" => FileType::Directory,
+                        "120000" => FileType::Symlink,
+                        _ => FileType::RegularFile,
+                    };
+                    children.push((entry.name, kind));
+                }
+            }
+        }
+        for (childName, kind) in overlay.listUpper(&location.relativePath) {
+            children.retain(|&(ref name, _)| *name != childName);
+            children.push((childName, kind));
+        }
+        children.sort_by(|first, second| first.0.cmp(&second.0));
+        Ok(children)
+    }
+    pub fn read(&mut self, location: &TreeLocation, offset: i64, size: u32) -> Result<Vec<u8>, c_int> {
+        let path = match self.resolve(location)? {
+            Some(TreeNode::Upper(upperPath)) => upperPath,
+            Some(TreeNode::Remote(ref entry)) if entry.kind == "blob" => self.fetchBlob(&location.root, &entry.sha)?,
+            Some(_) => return Err(EISDIR),
+            None => return Err(ENOENT),
+        };
+        passthrough::readAt(&path, offset, size).map_err(|error| passthrough::errorCode(&error))
+    }
+    pub fn readlink(&mut self, location: &TreeLocation) -> Result<Vec<u8>, c_int> {
+        match self.resolve(location)? {
+            Some(TreeNode::Upper(upperPath)) => fs::read_link(upperPath)
+                .map(|target| target.as_os_str().to_string_lossy().as_bytes().to_vec())
+                .map_err(|error| passthrough::errorCode(&error)),
+            Some(TreeNode::Remote(ref entry)) if entry.mode == "120000" => {
+                let blobPath = self.fetchBlob(&location.root, &entry.sha)?;
+                fs::read(blobPath).map_err(|error| passthrough::errorCode(&error))
+            },
+            Some(_) => Err(libc::EINVAL),
+            None => Err(ENOENT),
+        }
+    }
+    fn fetchBlob(&self, root: &TreeRoot, blobSha: &str) -> Result<PathBuf, c_int> {
+        github::fetchBlob(&root.repository, blobSha, &self.blobsDirectory).map_err(|error| {
+            eprintln!("gh-mount: {}", error);
+            EIO
+        })
+    }
+    // The first local edit pins the overlay to the commit it was made on.
+    fn prepareOverlay(&mut self, root: &TreeRoot) -> Result<Overlay, c_int> {
+        let overlay = self.overlay(root);
+        if overlay.baseCommit().is_none() {
+            let (commitSha, _, _) = self.rootCommit(root)?;
+            overlay.setBaseCommit(&commitSha).map_err(|error| {
+                eprintln!("gh-mount: {}", error);
+                EIO
+            })?;
+        }
+        Ok(overlay)
+    }
+    // Makes sure the directory holding `relativePath` exists in the upper directory.
+    fn copyUpParent(&mut self, location: &TreeLocation) -> Result<(), c_int> {
+        let overlay = self.prepareOverlay(&location.root)?;
+        let parentPath = overlay.upperPath(&location.relativePath);
+        fs::create_dir_all(parentPath.parent().unwrap()).map_err(|error| passthrough::errorCode(&error))
+    }
+    // Brings a remote entry into the upper directory so it can be modified.
+    pub fn copyUp(&mut self, location: &TreeLocation) -> Result<PathBuf, c_int> {
+        let entry = match self.resolve(location)? {
+            Some(TreeNode::Upper(upperPath)) => return Ok(upperPath),
+            Some(TreeNode::Remote(entry)) => entry,
+            Some(TreeNode::Root) => return Err(EPERM),
+            None => return Err(ENOENT),
+        };
+        self.copyUpParent(location)?;
+        let upperPath = self.overlay(&location.root).upperPath(&location.relativePath);
+        let result = match entry.mode.as_str() {
+            "040000" | "160000" => fs::create_dir(&upperPath),
+            "120000" => {
+                let blobPath = self.fetchBlob(&location.root, &entry.sha)?;
+                fs::read(blobPath).and_then(|target| {
+                    ::std::os::unix::fs::symlink(String::from_utf8_lossy(&target).to_string(), &upperPath)
+                })
+            },
+            mode => {
+                let blobPath = self.fetchBlob(&location.root, &entry.sha)?;
+                let permissions = if mode == "100755" { 0o755 } else { 0o644 };
+                fs::copy(blobPath, &upperPath)
+                    .and_then(|_| fs::set_permissions(&upperPath, fs::Permissions::from_mode(permissions)))
+            },
+        };
+        result.map_err(|error| passthrough::errorCode(&error))?;
+        Ok(upperPath)
+    }
+    pub fn write(&mut self, location: &TreeLocation, offset: i64, data: &[u8]) -> Result<(), c_int> {
+        let upperPath = self.copyUp(location)?;
+        passthrough::writeAt(&upperPath, offset, data).map_err(|error| passthrough::errorCode(&error))
+    }
+    pub fn setAttributes(&mut self, location: &TreeLocation, mode: Option<u32>, size: Option<u64>) -> Result<(), c_int> {
+        if mode.is_none() && size.is_none() {
+            return Ok(());
+        }
+        let upperPath = self.copyUp(location)?;
+        passthrough::setAttributes(&upperPath, mode, size).map_err(|error| passthrough::errorCode(&error))
+    }
+    fn checkName(location: &TreeLocation) -> Result<(), c_int> {
+        let name = location.relativePath.rsplit("/").next().unwrap_or("");
+        match name.starts_with(WHITEOUT_PREFIX) {
+            true => Err(EPERM),
+            false => Ok(()),
+        }
+    }
+    pub fn create(&mut self, location: &TreeLocation, mode: u32) -> Result<(), c_int> {
+        Trees::checkName(location)?;
+        if self.resolve(location)?.is_some() {
+            return Err(EEXIST);
+        }
+        self.copyUpParent(location)?;
+        let overlay = self.overlay(&location.root);
+        overlay.clearWhiteout(&location.relativePath);
+        OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .mode(mode & 0o7777)
+            .open(overlay.upperPath(&location.relativePath))
+            .map(|_| ())
+            .map_err(|error| passthrough::errorCode(&error))
+    }
+    pub fn mkdir(&mut self, location: &TreeLocation, mode: u32) -> Result<(), c_int> {
+        Trees::checkName(location)?;
+        if self.resolve(location)?.is_some() {
+            return Err(EEXIST);
+        }
+        self.copyUpParent(location)?;
+        let overlay = self.overlay(&location.root);
+        let upperPath = overlay.upperPath(&location.relativePath);
+        fs::create_dir(&upperPath)
+            .and_then(|_| fs::set_permissions(&upperPath, fs::Permissions::from_mode(mode & 0o7777)))
+            .map_err(|error| passthrough::errorCode(&error))?;
+        // a remote directory deleted before must not show its old children again
+        if overlay.clearWhiteout(&location.relativePath) {
+            overlay.markOpaque(&location.relativePath)?;
+        }
+        Ok(())
+    }
+    fn existsRemotely(&mut self, location: &TreeLocation) -> Result<bool, c_int> {
+        let overlay = self.overlay(&location.root);
+        if overlay.isHidden(&location.relativePath) {
+            return Ok(false);
+        }
+        Ok(self.remoteEntry(&location.root, &location.relativePath)?.is_some())
+    }
+    pub fn unlink(&mut self, location: &TreeLocation) -> Result<(), c_int> {
+        let node = self.resolve(location)?;
+        let existsRemotely = self.existsRemotely(location)?;
+        match node {
+            Some(TreeNode::Upper(upperPath)) => {
+                if fs::symlink_metadata(&upperPath).map(|metadata| metadata.is_dir()).unwrap_or(false) {
+                    return Err(EISDIR);
+                }
+                fs::remove_file(upperPath).map_err(|error| passthrough::errorCode(&error))?;
+            },
+            Some(TreeNode::Remote(ref entry)) if entry.kind == "blob" => (),
+            Some(_) => return Err(EISDIR),
+            None => return Err(ENOENT),
+        }
+        if existsRemotely {
+            let overlay = self.prepareOverlay(&location.root)?;
+            overlay.whiteout(&location.relativePath)?;
+        }
+        Ok(())
+    }
+    pub fn rmdir(&mut self, location: &TreeLocation) -> Result<(), c_int> {
+        let node = self.resolve(location)?;
+        match node {
+            Some(TreeNode::Upper(ref upperPath)) if !upperPath.is_dir() => return Err(ENOTDIR),
+            Some(TreeNode::Remote(ref entry)) if entry.kind == "blob" => return Err(ENOTDIR),
+            Some(TreeNode::Root) => return Err(EPERM),
+            Some(_) => (),
+            None => return Err(ENOENT),
+        }
+        if self.list(location)?.len() > 0 {
+            return Err(ENOTEMPTY);
+        }
+        let existsRemotely = self.existsRemotely(location)?;
+        if let Some(TreeNode::Upper(upperPath)) = node {
+            // only whiteouts and the opaque marker can be left in there
+            fs::remove_dir_all(upperPath).map_err(|error| passthrough::errorCode(&error))?;
+        }
+        if existsRemotely {
+            let overlay = self.prepareOverlay(&location.root)?;
+            overlay.whiteout(&location.relativePath)?;
+        }
+        Ok(())
+    }
+    // Renames inside one tree. Remote directories are not copied up recursively,
+    // EXDEV makes `mv` fall back to copying them, like overlayfs does.
+    pub fn rename(&mut self, from: &TreeLocation, to: &TreeLocation) -> Result<(), c_int> {
+        if from.root.repository != to.root.repository || from.root.reference != to.root.reference {
+            return Err(EXDEV);
+        }
+        Trees::checkName(to)?;
+        match self.resolve(from)? {
+            Some(TreeNode::Remote(ref entry)) if entry.kind != "blob" => return Err(EXDEV),
+            Some(TreeNode::Root) => return Err(EPERM),
+            // a directory with local edits still shows the remote files it was not
+            // copied up with, renaming only the upper directory would lose them
+            Some(TreeNode::Upper(ref upperPath)) if upperPath.is_dir() => {
+                let showsRemoteChildren = !self.overlay(&from.root).isOpaque(&from.relativePath);
+                match self.remoteEntry(&from.root, &from.relativePath)? {
+                    Some(ref entry) if entry.kind != "blob" && showsRemoteChildren => return Err(EXDEV),
+                    _ => (),
+                }
+            },
+            Some(_) => (),
+            None => return Err(ENOENT),
+        }
+        let sourceExistsRemotely = self.existsRemotely(from)?;
+        let sourcePath = self.copyUp(from)?;
+        self.copyUpParent(to)?;
+        let overlay = self.overlay(&to.root);
+        if let Some(TreeNode::Remote(ref entry)) = self.resolve(to)? {
+            if entry.kind != "blob" {
+                return Err(EISDIR);
+            }
+        }
+        overlay.clearWhiteout(&to.relativePath);
+        fs::rename(&sourcePath, overlay.upperPath(&to.relativePath)).map_err(|error| passthrough::errorCode(&error))?;
+        if sourceExistsRemotely {
+            overlay.whiteout(&from.relativePath)?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,456 @@
+use fuser::{consts, FileAttr, FileType};
+use libc::{c_int, EINVAL, EIO, EISDIR, ENOENT, ENOTSUP, EPERM};
+use std::collections::HashMap;
+use std::fs;
+use std::path::PathBuf;
+use std::process;
+use std::time::SystemTime;
+
+use actions;
+use attributes::{self, Owner};
+use commits;
+use compare;
+use gists;
+use github;
+use history;
+use issues;
+use metadata;
+use passthrough;
+use pulls;
+use refs;
+use releases;
+use snapshots;
+use storage::Storage;
+use tree::Trees;
+
+// Special directories of every repository, next to the files of its tree. They are
+// not listed in the repository directory (so `grep -r` and `find` don't walk into
+// the API) but can be entered by name.
+pub const REPOSITORY_VIEWS: &[&str] = &[".actions", ".at", ".commits", ".compare", ".gh", ".history", ".issues", ".pulls", ".refs", ".releases"];
+// The same, next to the repositories of an account.
+pub const ACCOUNT_VIEWS: &[&str] = &[".gists"];
+
+// API responses are reused for this many seconds, so `ls` followed by `cat` doesn't
+// ask GitHub twice.
+const CACHE_TTL: u64 = 30;
+
+pub enum ViewNode {
+    Directory,
+    // contents generated from API data
+    File(Vec<u8>),
+    // the same for contents that are costly to generate (job logs, blame), only
+    // generated when the file is opened: the size is 0 until then, and reads bypass
+    // the page cache
+    Deferred(u64),
+    Symlink(String),
+    // a file of a repository, downloaded to the blob cache when it is read
+    Blob {
+        repository: String,
+        sha: String,
+        size: u64,
+    },
+    // a release asset, workflow artifact or gist file, downloaded from `endpoint` to the
+    // downloads cache as `fileName` when it is read
+    Download {
+        endpoint: String,
+        fileName: String,
+        size: u64,
+    },
+    // a file whose contents are sent to GitHub when it is closed, with the file
+    // buffering what was written so far (None before the first write)
+    Input(Option<PathBuf>),
+}
+
+// `owner/repo/.view/rest...`, or `owner/.view/rest...` for account views (their
+// `repository` is empty). `owner/repo@{date}/rest...` is `owner/repo/.at/date/rest...`.
+pub struct ViewPath {
+    pub fullPath: String,
+    pub account: String,
+    pub repository: String,
+    pub view: String,
+    pub rest: Vec<String>,
+}
+
+pub fn parseViewPath(fullPath: &str) -> Option<ViewPath> {
+    let pathParts: Vec<&str> = fullPath.split("/").collect();
+    if let Some((name, date)) = pathParts.get(1).and_then(|pathPart| dateAlias(pathPart)) {
+        let mut rest = vec![date.to_string()];
+        rest.extend(pathParts[2..].iter().map(|pathPart| pathPart.to_string()));
+        return Some(ViewPath {
+            fullPath: fullPath.to_string(),
+            account: pathParts[0].to_string(),
+            repository: format!("{}/{}", pathParts[0], name),
+            view: ".at".to_string(),
+            rest: rest,
+        });
+    }
+    let (repository, viewIndex) = if pathParts.len() >= 2 && ACCOUNT_VIEWS.contains(&pathParts[1]) {
+        (String::new(), 1)
+    } else if pathParts.len() >= 3 && REPOSITORY_VIEWS.contains(&pathParts[2]) {
+        (pathParts[..2].join("/"), 2)
+    } else {
+        return None;
+    };
+    Some(ViewPath {
+        fullPath: fullPath.to_string(),
+        account: pathParts[0].to_string(),
+        repository: repository,
+        view: pathParts[viewIndex].to_string(),
+        rest: pathParts[viewIndex + 1..].iter().map(|pathPart| pathPart.to_string()).collect(),
+    })
+}
+
+// `name@{date}` -> (name, date)
+fn dateAlias(name: &str) -> Option<(&str, &str)> {
+    if !name.ends_with("}") {
+        return None;
+    }
+    let index = name.find("@{")?;
+    match index {
+        0 => None,
+        _ => Some((&name[..index], &name[index + 2..name.len() - 1])),
+    }
+}
+
+pub fn isViewPath(fullPath: &str) -> bool {
+    parseViewPath(fullPath).is_some()
+}
+
+// Names from GitHub (refs, labels...) can contain `/`, which is `%2F` in a file name.
+pub fn encodeName(name: &str) -> String {
+    name.replace("%", "%25").replace("/", "%2F")
+}
+
+pub fn decodeName(name: &str) -> String {
+    name.replace("%2F", "/").replace("%25", "%")
+}
+
+// The children of `directory` in a tree made of the files at `paths`, for the
+// directories showing only some files of a repository.
+pub fn pathChildren(paths: &[String], directory: &str) -> Vec<(String, FileType)> {
+    let prefix = match directory.len() {
+        0 => String::new(),
+        _ => directory.to_string() + "/",
+    };
+    let mut children: Vec<(String, FileType)> = Vec::new();
+    for path in paths {
+        if !path.starts_with(&prefix) {
+            continue;
+        }
+        let remainder = &path[prefix.len()..];
+        let child = match remainder.find("/") {
+            Some(index) => (remainder[..index].to_string(), FileType::Directory),
+            None => (remainder.to_string(), FileType::RegularFile),
+        };
+        if !children.iter().any(|&(ref name, _)| *name == child.0) {
+            children.push(child);
+        }
+    }
+    children.sort_by(|first, second| first.0.cmp(&second.0));
+    children
+}
+
+// Errors of gh calls made on behalf of a FUSE request.
+pub fn errorCode(error: &str) -> c_int {
+    eprintln!("gh-mount: {}", error);
+    if github::isNotFound(error) {
+        ENOENT
+    } else if github::isForbidden(error) {
+        libc::EACCES
+    } else {
+        EIO
+    }
+}
+
+pub fn isFresh(fetchedAt: SystemTime) -> bool {
+    fetchedAt.elapsed().map(|elapsed| elapsed.as_secs() < CACHE_TTL).unwrap_or(false)
+}
+
+pub struct Views {
+    pub blobsDirectory: PathBuf,
+    pub downloadsDirectory: PathBuf,
+    // `gh` arguments -> (fetched at, output)
+    cache: HashMap<String, (SystemTime, Vec<u8>)>,
+    // `user.github.source` set on a `.refs/branches` or `.refs/tags` directory
+    pub refSources: HashMap<String, String>,
+    // files buffering what was written to input files that were not closed yet, in
+    // the downloads directory so big release assets don't stay in memory
+    pub pendingInputs: HashMap<String, PathBuf>,
+    lastInput: u64,
+    // contents of the deferred files that are open
+    opened: HashMap<String, Vec<u8>>,
+    owner: Owner,
+}
+
+impl Views {
+    pub fn new(storage: &Storage, owner: Owner) -> Views {
+        Views {
+            blobsDirectory: storage.blobsDirectory.clone(),
+            downloadsDirectory: storage.downloadsDirectory.clone(),
+            cache: HashMap::new(),
+            refSources: HashMap::new(),
+            pendingInputs: HashMap::new(),
+            lastInput: 0,
+            opened: HashMap::new(),
+            owner: owner,
+        }
+    }
+    // `gh` output as is, cached for CACHE_TTL.
+    pub fn ghFile(&mut self, args: &[&str]) -> Result<Vec<u8>, c_int> {
+        let key = args.join(" ");
+        if let Some(&(fetchedAt, ref output)) = self.cache.get(&key) {
+            if isFresh(fetchedAt) {
+                return Ok(output.clone());
+            }
+        }
+        let output = github::gh(args).map_err(|error| errorCode(&error))?;
+        // expired responses go away here, so the cache only holds the last CACHE_TTL
+        self.cache.retain(|_, &mut (fetchedAt, _)| isFresh(fetchedAt));
+        self.cache.insert(key, (SystemTime::now(), output.clone()));
+        Ok(output)
+    }
+    pub fn gh(&mut self, args: &[&str]) -> Result<String, c_int> {
+        self.ghFile(args).map(|output| String::from_utf8_lossy(&output).trim_end().to_string())
+    }
+    // Drops the cached responses about a repository after it was changed through the mount.
+    pub fn invalidate(&mut self, fullRepositoryName: &str) {
+        let prefix = format!("repos/{}/", fullRepositoryName);
+        self.cache.retain(|key, _| !key.contains(&prefix));
+    }
+    pub fn resolve(&mut self, trees: &mut Trees, path: &ViewPath) -> Result<Option<ViewNode>, c_int> {
+        match path.view.as_str() {
+            ".actions" => actions::resolve(self, path),
+            ".at" => snapshots::resolve(self, trees, path),
+            ".commits" => commits::resolve(self, trees, path),
+            ".compare" => compare::resolve(self, trees, path),
+            ".gh" => metadata::resolve(self, path),
+            ".gists" => gists::resolve(self, path),
+            ".history" => history::resolve(self, trees, path),
+            ".issues" => issues::resolve(self, path),
+            ".pulls" => pulls::resolve(self, trees, path),
+            ".refs" => refs::resolve(self, trees, path),
+            ".releases" => releases::resolve(self, path),
+            _ => Ok(None),
+        }
+    }
+    pub fn list(&mut self, trees: &mut Trees, path: &ViewPath) -> Result<Vec<(String, FileType)>, c_int> {
+        match path.view.as_str() {
+            ".actions" => actions::list(self, path),
+            ".at" => snapshots::list(self, path),
+            ".commits" => commits::list(self, path),
+            ".compare" => compare::list(self, path),
+            ".gh" => metadata::list(self, path),
+            ".gists" => gists::list(self, path),
+            ".history" => history::list(self, trees, path),
+            ".issues" => issues::list(self, path),
+            ".pulls" => pulls::list(self, path),
+            ".refs" => refs::list(self, path),
+            ".releases" => releases::list(self, path),
+            _ => Err(ENOENT),
+        }
+    }
+    pub fn deferredNode(&self, fullPath: &str) -> ViewNode {
+        ViewNode::Deferred(self.opened.get(fullPath).map(|contents| contents.len() as u64).unwrap_or(0))
+    }
+    fn render(&mut self, trees: &mut Trees, path: &ViewPath) -> Result<Vec<u8>, c_int> {
+        match path.view.as_str() {
+            ".actions" => actions::render(self, path),
+            ".history" => history::render(self, trees, path),
+            _ => Err(ENOENT),
+        }
+    }
+    // The FOPEN_* flags to open `path` with.
+    pub fn open(&mut self, trees: &mut Trees, path: &ViewPath) -> Result<u32, c_int> {
+        match self.resolve(trees, path)? {
+            Some(ViewNode::Deferred(_)) => {
+                let contents = self.render(trees, path)?;
+                self.opened.insert(path.fullPath.clone(), contents);
+                Ok(consts::FOPEN_DIRECT_IO)
+            },
+            Some(_) => Ok(0),
+            None => Err(ENOENT),
+        }
+    }
+    pub fn read(&mut self, trees: &mut Trees, path: &ViewPath, offset: i64, size: u32) -> Result<Vec<u8>, c_int> {
+        match self.resolve(trees, path)? {
+            Some(ViewNode::Deferred(_)) => {
+                let contents = match self.opened.get(&path.fullPath) {
+                    Some(contents) => contents.clone(),
+                    None => self.render(trees, path)?,
+                };
+                let start = (offset.max(0) as usize).min(contents.len());
+                let end = (start + size as usize).min(contents.len());
+                Ok(contents[start..end].to_vec())
+            },
+            Some(ViewNode::Input(Some(inputPath))) => {
+                passthrough::readAt(&inputPath, offset, size).map_err(|error| passthrough::errorCode(&error))
+            },
+            Some(ViewNode::Input(None)) => Ok(Vec::new()),
+            Some(ViewNode::File(contents)) => {
+                let start = (offset.max(0) as usize).min(contents.len());
+                let end = (start + size as usize).min(contents.len());
+                Ok(contents[start..end].to_vec())
+            },
+            Some(ViewNode::Blob { repository, sha, .. }) => {
+                let blobPath = github::fetchBlob(&repository, &sha, &self.blobsDirectory).map_err(|error| errorCode(&error))?;
+                passthrough::readAt(&blobPath, offset, size).map_err(|error| passthrough::errorCode(&error))
+            },
+            Some(ViewNode::Download { endpoint, fileName, .. }) => {
+                let downloadPath = self.downloadsDirectory.join(&fileName);
+                if !downloadPath.exists() {
+                    github::download(&endpoint, &downloadPath).map_err(|error| errorCode(&error))?;
+                }
+                passthrough::readAt(&downloadPath, offset, size).map_err(|error| passthrough::errorCode(&error))
+            },
+            Some(ViewNode::Directory) => Err(EISDIR),
+            Some(ViewNode::Symlink(_)) => Err(EINVAL),
+            None => Err(ENOENT),
+        }
+    }
+    pub fn readlink(&mut self, trees: &mut Trees, path: &ViewPath) -> Result<Vec<u8>, c_int> {
+        match self.resolve(trees, path)? {
+            Some(ViewNode::Symlink(target)) => Ok(target.into_bytes()),
+            Some(_) => Err(EINVAL),
+            None => Err(ENOENT),
+        }
+    }
+    pub fn create(&mut self, path: &ViewPath) -> Result<(), c_int> {
+        match path.view.as_str() {
+            ".issues" => issues::create(self, path),
+            ".releases" => releases::create(self, path),
+            _ => Err(EPERM),
+        }
+    }
+    // Starts buffering an input file, from `create()` or its first write.
+    pub fn startInput(&mut self, fullPath: &str) -> Result<PathBuf, c_int> {
+        self.lastInput += 1;
+        let inputPath = self.downloadsDirectory.join(format!("input-{}-{}", process::id(), self.lastInput));
+        fs::write(&inputPath, b"").map_err(|error| passthrough::errorCode(&error))?;
+        self.pendingInputs.insert(fullPath.to_string(), inputPath.clone());
+        Ok(inputPath)
+    }
+    pub fn inputNode(&self, fullPath: &str) -> Option<ViewNode> {
+        self.pendingInputs.get(fullPath).map(|inputPath| ViewNode::Input(Some(inputPath.clone())))
+    }
+    // The file buffering `path`, only resolved on the first write so the following
+    // ones don't ask GitHub again.
+    fn inputPath(&mut self, trees: &mut Trees, path: &ViewPath) -> Result<PathBuf, c_int> {
+        if let Some(inputPath) = self.pendingInputs.get(&path.fullPath) {
+            return Ok(inputPath.clone());
+        }
+        match self.resolve(trees, path)? {
+            Some(ViewNode::Input(_)) => self.startInput(&path.fullPath),
+            Some(_) => Err(EPERM),
+            None => Err(ENOENT),
+        }
+    }
+    // Input files are buffered until they are closed.
+    pub fn write(&mut self, trees: &mut Trees, path: &ViewPath, offset: i64, data: &[u8]) -> Result<(), c_int> {
+        let inputPath = self.inputPath(trees, path)?;
+        passthrough::writeAt(&inputPath, offset, data).map_err(|error| passthrough::errorCode(&error))
+    }
+    pub fn truncate(&mut self, trees: &mut Trees, path: &ViewPath, size: u64) -> Result<(), c_int> {
+        let inputPath = self.inputPath(trees, path)?;
+        passthrough::setAttributes(&inputPath, None, Some(size)).map_err(|error| passthrough::errorCode(&error))
+    }
+    fn discardInput(&mut self, fullPath: &str) {
+        if let Some(inputPath) = self.pendingInputs.remove(fullPath) {
+            let _ = fs::remove_file(inputPath);
+        }
+    }
+    // Sends what was written to an input file, the error is what close() returns.
+    // Nothing is sent before the first write: shells close a duplicate of the file
+    // descriptor right after opening it.
+    pub fn flush(&mut self, path: &ViewPath) -> Result<(), c_int> {
+        let inputPath = match self.pendingInputs.get(&path.fullPath) {
+            Some(inputPath) if fs::metadata(inputPath).map(|metadata| metadata.len() > 0).unwrap_or(false) => inputPath.clone(),
+            _ => return Ok(()),
+        };
+        let result = match path.view.as_str() {
+            ".issues" => issues::submit(self, path, &inputPath),
+            ".releases" => releases::submit(self, path, &inputPath),
+            _ => Ok(()),
+        };
+        self.discardInput(&path.fullPath);
+        result
+    }
+    // The last close of an input file: a file that was created but never written
+    // goes away.
+    pub fn release(&mut self, path: &ViewPath) -> Result<(), c_int> {
+        let result = self.flush(path);
+        self.discardInput(&path.fullPath);
+        self.opened.remove(&path.fullPath);
+        result
+    }
+    // Returns the path of the directory that was created, which can differ from the
+    // requested one (`mkdir .refs/branches/name@source` creates `name`).
+    pub fn mkdir(&mut self, trees: &mut Trees, path: &ViewPath) -> Result<String, c_int> {
+        match path.view.as_str() {
+            ".refs" => refs::mkdir(self, trees, path),
+            ".releases" => releases::mkdir(self, path),
+            _ => Err(EPERM),
+        }
+    }
+    pub fn rmdir(&mut self, trees: &mut Trees, path: &ViewPath) -> Result<(), c_int> {
+        match path.view.as_str() {
+            ".refs" => refs::rmdir(self, trees, path),
+            _ => Err(EPERM),
+        }
+    }
+    pub fn setAttribute(&mut self, path: &ViewPath, name: &str, value: &[u8]) -> Result<(), c_int> {
+        match path.view.as_str() {
+            ".refs" => refs::setAttribute(self, path, name, value),
+            _ => Err(ENOTSUP),
+        }
+    }
+    pub fn attr(&self, ino: u64, node: &ViewNode) -> FileAttr {
+        let ts = SystemTime::now();
+        let (kind, perm, size) = match *node {
+            ViewNode::Directory => (FileType::Directory, 0o755, 0),
+            ViewNode::File(ref contents) => (FileType::RegularFile, 0o444, contents.len() as u64),
+            ViewNode::Symlink(ref target) => (FileType::Symlink, 0o777, target.len() as u64),
+            ViewNode::Blob { size, .. } | ViewNode::Download { size, .. } | ViewNode::Deferred(size) => {
+                (FileType::RegularFile, 0o444, size)
+            },
+            ViewNode::Input(ref inputPath) => {
+                let size = inputPath.as_ref().and_then(|inputPath| fs::metadata(inputPath).ok()).map(|metadata| metadata.len());
+                (FileType::RegularFile, 0o644, size.unwrap_or(0))
+            },
+        };
+        attributes::nodeAttr(ino, kind, perm, size, ts, self.owner)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parsesViewPaths() {
+        let path = parseViewPath("cli/cli/.issues/closed/12-crash.md").unwrap();
+        assert_eq!(path.account, "cli");
+        assert_eq!(path.repository, "cli/cli");
+        assert_eq!(path.view, ".issues");
+        assert_eq!(path.rest, vec!["closed", "12-crash.md"]);
+        let path = parseViewPath("cli/.gists").unwrap();
+        assert_eq!(path.repository, "");
+        assert_eq!(path.view, ".gists");
+        assert!(path.rest.is_empty());
+        assert!(parseViewPath("cli/cli/src/.issues").is_none());
+        assert!(parseViewPath("cli/cli/.unknown").is_none());
+        assert!(parseViewPath("cli/cli").is_none());
+    }
+
+    #[test]
+    fn parsesDateAliases() {
+        assert_eq!(dateAlias("cli@{2024-01-31}"), Some(("cli", "2024-01-31")));
+        assert_eq!(dateAlias("a@b@{2024-01-31}"), Some(("a@b", "2024-01-31")));
+        assert_eq!(dateAlias("@{2024-01-31}"), None);
+        assert_eq!(dateAlias("cli@{2024-01-31"), None);
+        assert_eq!(dateAlias("cli"), None);
+        let path = parseViewPath("cli/cli@{2024-01-31}/src/main.rs").unwrap();
+        assert_eq!(path.repository, "cli/cli");
+        assert_eq!(path.view, ".at");
+        assert_eq!(path.rest, vec!["2024-01-31", "src", "main.rs"]);
+    }
+}